@@ -0,0 +1,97 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use crate::emulator::Emulator;
+use crate::wav::{SampleFormat, WavWriter};
+
+/// Records every sample the ACP hands the DAC, at the rate it's being interrupted at.
+/// The file's only made once the first sample arrives, since that's when the rate's known.
+pub struct AudioCapture {
+    path: PathBuf,
+    wav: Option<WavWriter<BufWriter<File>>>,
+    sample_rate: f64,
+}
+
+impl AudioCapture {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), wav: None, sample_rate: 0.0 }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.wav.as_ref().map_or(0, |wav| wav.samples())
+    }
+
+    pub(crate) fn push(&mut self, sample: u8, sample_rate: f64) -> io::Result<()> {
+        let wav = match &mut self.wav {
+            Some(wav) => {
+                if sample_rate != self.sample_rate {
+                    // a wav can only have one rate, and splitting files would make lining recordings up a pain
+                    warn!("acp sample rate changed to {sample_rate:.0}hz mid-capture, {} will play back at {}hz", self.path.display(), wav.sample_rate());
+                    self.sample_rate = sample_rate;
+                }
+                wav
+            }
+            None => {
+                self.sample_rate = sample_rate;
+                self.wav.insert(WavWriter::create(&self.path, sample_rate.round() as u32, SampleFormat::U8)?)
+            }
+        };
+        wav.write_u8(sample)
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.wav {
+            Some(wav) => wav.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Emulator {
+    /// Starts recording the raw DAC stream to `raw`, and whatever the audio output plays to `output`.
+    /// Either can be left out. Replaces any capture that's already running.
+    pub fn start_audio_capture(&mut self, raw: Option<&Path>, output: Option<&Path>) -> io::Result<()> {
+        self.stop_audio_capture();
+
+        if let Some(path) = output {
+            let Some(audio) = &mut self.audio_out else {
+                return Err(io::Error::other("there's no audio output to record"))
+            };
+            let wav = WavWriter::create(path, audio.output_sample_rate(), SampleFormat::I16)?;
+            audio.capture_output(Some(wav));
+        }
+        self.audio_capture = raw.map(AudioCapture::new);
+        Ok(())
+    }
+
+    pub fn stop_audio_capture(&mut self) {
+        if let Some(mut capture) = self.audio_capture.take() {
+            match capture.finish() {
+                Ok(()) => warn!("saved {} samples to {}", capture.samples(), capture.path().display()),
+                Err(e) => warn!("couldn't finish {}: {e}", capture.path().display()),
+            }
+        }
+        if let Some(audio) = &mut self.audio_out {
+            audio.capture_output(None);
+        }
+    }
+
+    pub fn is_capturing_audio(&self) -> bool {
+        self.audio_capture.is_some() || self.audio_out.as_ref().is_some_and(|audio| audio.is_capturing_output())
+    }
+
+    pub(crate) fn capture_sample(&mut self, sample: u8, sample_rate: f64) {
+        let Some(capture) = &mut self.audio_capture else {
+            return
+        };
+        if let Err(e) = capture.push(sample, sample_rate) {
+            warn!("stopping audio capture, couldn't write {}: {e}", capture.path().display());
+            self.audio_capture = None;
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Where the ACP's samples end up. The emulator runs the ACP the same way whichever output it has
+//! (or with none at all), so swapping outputs never changes timing.
+//!
+//! Outputs that need a sound device live in the frontend; the ones here work anywhere.
+
+use std::any::Any;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use tracing::warn;
+use crate::audio_capture::AudioCapture;
+use crate::wav::WavWriter;
+
+/// Where the ACP's output goes. The emulator hands over one sample per ACP IRQ,
+/// along with the rate the ACP is currently being interrupted at.
+pub trait AudioOutput {
+    fn push_sample(&mut self, sample: u8, sample_rate: f64);
+
+    /// The rate this output plays at, after any resampling.
+    fn output_sample_rate(&self) -> u32 {
+        48000
+    }
+
+    /// Starts recording what this output plays, after resampling. `None` stops (and finishes) the recording.
+    fn capture_output(&mut self, wav: Option<WavWriter<BufWriter<File>>>) {
+        if wav.is_some() {
+            warn!("this audio output can't record what it plays");
+        }
+    }
+
+    fn is_capturing_output(&self) -> bool {
+        false
+    }
+
+    /// How much audio is buffered but not played yet, for outputs that play in real time.
+    fn queued_seconds(&self) -> Option<f64> {
+        None
+    }
+
+    /// Plays slightly slower (above 1.0) or faster than the ACP's real rate, so the buffer can drift
+    /// back toward where it should be without anyone hearing it.
+    fn set_rate_adjustment(&mut self, _ratio: f64) {}
+
+    /// For frontends to reach settings only their own outputs have, like how a sound card's stream is resampled.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+/// Throws every sample away, for machines with no sound device.
+#[derive(Debug, Default)]
+pub struct NullAudioOutput;
+
+impl AudioOutput for NullAudioOutput {
+    fn push_sample(&mut self, _sample: u8, _sample_rate: f64) {}
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Writes the ACP's samples straight to a wav, at the rate they're made.
+pub struct FileAudioOutput {
+    capture: Option<AudioCapture>,
+}
+
+impl FileAudioOutput {
+    pub fn new(path: &Path) -> Self {
+        Self { capture: Some(AudioCapture::new(path)) }
+    }
+}
+
+impl AudioOutput for FileAudioOutput {
+    fn push_sample(&mut self, sample: u8, sample_rate: f64) {
+        let Some(capture) = &mut self.capture else {
+            return
+        };
+        if let Err(e) = capture.push(sample, sample_rate) {
+            warn!("dropping audio, couldn't write {}: {e}", capture.path().display());
+            self.capture = None;
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -1,9 +1,9 @@
-use std::intrinsics::{add_with_overflow, wrapping_add};
-use std::time::Instant;
-use tracing::{debug, info, warn};
-use crate::emulator::gametank_bus::{CpuBus};
+use tracing::debug;
+use crate::gametank_bus::CpuBus;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+use crate::watchpoint::{WatchKind, WatchTarget};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Blitter {
     // start_time: Instant,
 
@@ -29,24 +29,8 @@ pub struct Blitter {
 }
 
 impl Blitter {
-    pub fn default() -> Self {
-        Self {
-            src_y: 0,
-            dst_y: 0,
-            height: 0,
-            flip_y: false,
-            src_x: 0,
-            dst_x: 0,
-            width: 0,
-            flip_x: false,
-            offset_x: 0,
-            offset_y: 0,
-            color_fill: false,
-            color: 0,
-            blitting: false,
-            cycles: 0,
-            irq_trigger: false,
-        }
+    pub fn is_blitting(&self) -> bool {
+        self.blitting
     }
 
     pub fn clear_irq_trigger(&mut self) -> bool {
@@ -132,11 +116,8 @@ impl Blitter {
             let mut src_x_mod = self.src_x;
             let mut src_y_mod = self.src_y;
 
-            let mut blit_src_x = src_x_mod as usize;
-            let mut blit_src_y = src_y_mod as usize;
-            blit_src_x = (src_x_mod.wrapping_add(self.offset_x)) as usize;
-            blit_src_y = (src_y_mod.wrapping_add(self.offset_y)) as usize;
-
+            let mut blit_src_x;
+            let mut blit_src_y;
 
             if self.flip_x {
                 src_x_mod = !src_x_mod;
@@ -166,37 +147,67 @@ impl Blitter {
                 quad += 128*128;
             }
 
-            bus.vram_banks[vram_page][blit_src_x + blit_src_y*128 + quad]
+            let index = blit_src_x + blit_src_y*128 + quad;
+            let color = bus.vram_banks[vram_page][index];
+            bus.watchpoints.check_blitter(WatchTarget::Vram(vram_page as u8, index as u16), WatchKind::Read, color);
+            color
         };
 
-        let out_x = wrapping_add(self.dst_x, self.offset_x) as usize;
-        let out_y = (self.dst_y + self.offset_y) as usize;
-        let out_fb = bus.system_control.banking_register.framebuffer() as usize;
+        let out_x = self.dst_x.wrapping_add(self.offset_x);
+        let out_y = self.dst_y.wrapping_add(self.offset_y);
+        let banking = &bus.system_control.banking_register;
+        let out_fb = banking.framebuffer() as usize;
 
-        if out_x >= 128 || out_y >= 128 {
+        // anything past 127 is off screen. if clipping's off, the framebuffer address just drops the top bit,
+        // so the blit wraps around to the other side
+        if (out_x >= 128 && banking.clip_blits_h()) || (out_y >= 128 && banking.clip_blits_v()) {
             self.offset_x = self.offset_x.wrapping_add(1);
             return
         }
+        let out_x = out_x as usize & 0x7F;
+        let out_y = out_y as usize & 0x7F;
 
         // write to active framebuffer, if not transparent
         if bus.system_control.dma_flags.dma_opaque() || color != 0 {
             bus.framebuffers[out_fb].borrow_mut()[out_x + out_y*128] = color;
+            bus.watchpoints.check_blitter(WatchTarget::Framebuffer(out_fb as u8, (out_x + out_y*128) as u16), WatchKind::Write, color);
         }
 
         // increment x offset
         self.offset_x = self.offset_x.wrapping_add(1);
     }
 
+    /// Like [`Blitter::cycle`], but a blit that starts runs to completion (irq and all) right away.
     pub fn instant_blit(&mut self, bus: &mut CpuBus) {
-        // TODO:
-        // on blit start, blit until done
-        // if !self.blitting && bus.blitter.start != 0 {
-        //     loop {
-        //         self.cycle(bus);
-        //         if !self.blitting {
-        //             break;
-        //         }
-        //     }
-        // }
+        self.cycle(bus);
+        while self.blitting {
+            self.cycle(bus);
+        }
     }
 }
+
+impl Snapshot for Blitter {
+    fn save(&self, w: &mut StateWriter) {
+        for v in [self.src_y, self.dst_y, self.height, self.src_x, self.dst_x, self.width, self.offset_x, self.offset_y, self.color] {
+            w.u8(v);
+        }
+        for v in [self.flip_y, self.flip_x, self.color_fill, self.blitting, self.irq_trigger] {
+            w.bool(v);
+        }
+        w.i32(self.cycles);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        for v in [&mut self.src_y, &mut self.dst_y, &mut self.height, &mut self.src_x, &mut self.dst_x, &mut self.width, &mut self.offset_x, &mut self.offset_y, &mut self.color] {
+            *v = r.u8()?;
+        }
+        for v in [&mut self.flip_y, &mut self.flip_x, &mut self.color_fill, &mut self.blitting, &mut self.irq_trigger] {
+            *v = r.bool()?;
+        }
+        self.cycles = r.i32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,273 @@
+//! Blits into a known framebuffer, compared against the reference images in `testdata/blitter`.
+//!
+//! References are text, one character per pixel: `-` is the untouched background, `.` is color 0,
+//! and colors 1-15 are hex digits. Run with `BLESS=1` to rewrite them after an intended change.
+//!
+//! None of them are hardware captures yet. They were made by this emulator with `BLESS=1` and read over
+//! by hand against their descriptions, so they catch regressions but not bugs that were already there.
+//! Each file says where it came from on its second line; replace it with a capture when there is one,
+//! and change that line to say so.
+
+use std::path::PathBuf;
+use crate::blitter::Blitter;
+use crate::gametank_bus::CpuBus;
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoint};
+
+const BACKGROUND: u8 = 0xFF;
+/// what `BLESS=1` says about the references it writes
+const PROVENANCE: &str = "# generated by gametank-core's blitter with BLESS=1, not captured from hardware";
+/// the glyph's top left corner in vram
+const GLYPH: (u8, u8) = (16, 16);
+
+/// A 16x16 glyph with no symmetry, so flips are obvious. `.` pixels are transparent.
+const GLYPH_ROWS: [&str; 16] = [
+    "9999999999999999",
+    "9aaaaaaaaaaaaaa9",
+    "9a............a9",
+    "9a.bbbbbbbbbb.a9",
+    "9a.b..........a9",
+    "9a.b..........a9",
+    "9a.bbbbbb.....a9",
+    "9a.b..........a9",
+    "9a.b..........a9",
+    "9a.b....cc....a9",
+    "9a.b....cc....a9",
+    "9a.b..........a9",
+    "9a.b.........da9",
+    "9a...........da9",
+    "9aaaaaaaaaaaaaa9",
+    "999999999999999e",
+];
+
+fn pixel_char(color: u8) -> char {
+    match color {
+        BACKGROUND => '-',
+        0 => '.',
+        1..=15 => char::from_digit(color as u32, 16).unwrap(),
+        _ => panic!("color {color:02X} has no character, tests should stick to 0-15"),
+    }
+}
+
+fn char_pixel(c: char) -> u8 {
+    match c {
+        '-' => BACKGROUND,
+        '.' => 0,
+        _ => c.to_digit(16).expect("bad pixel in reference image") as u8,
+    }
+}
+
+/// The vram index of a pixel. Each 128x128 quadrant is stored contiguously, like on the real thing.
+fn vram_index(x: u8, y: u8) -> usize {
+    let (x, y) = (x as usize, y as usize);
+    (x % 128) + (y % 128) * 128 + (x / 128) * 128 * 128 + (y / 128) * 2 * 128 * 128
+}
+
+/// A bus with the glyph at [`GLYPH`] on vram page 0, and a 4x4 checkerboard everywhere else,
+/// two colors per quadrant (1-2, 3-4, 5-6, 7-8) so quadrant crossings show up.
+fn setup() -> (Blitter, CpuBus) {
+    let mut bus = CpuBus::with_seed(0);
+    for y in 0..=255u8 {
+        for x in 0..=255u8 {
+            let quadrant = (x / 128) + (y / 128) * 2;
+            bus.vram_banks[0][vram_index(x, y)] = 1 + quadrant * 2 + (x / 4 + y / 4) % 2;
+        }
+    }
+    for (dy, row) in GLYPH_ROWS.iter().enumerate() {
+        for (dx, c) in row.chars().enumerate() {
+            bus.vram_banks[0][vram_index(GLYPH.0 + dx as u8, GLYPH.1 + dy as u8)] = char_pixel(c);
+        }
+    }
+    for fb in &bus.framebuffers {
+        fb.borrow_mut().fill(BACKGROUND);
+    }
+
+    // blitter on, gcarry on, everything else off
+    bus.system_control.dma_flags.0 = 0;
+    bus.system_control.dma_flags.set_dma_enable(true);
+    bus.system_control.dma_flags.set_dma_gcarry(true);
+    bus.system_control.banking_register.0 = 0;
+
+    (Blitter::default(), bus)
+}
+
+/// Writes the blitter registers the way a game would, and cycles until the blit's done.
+fn blit(blitter: &mut Blitter, bus: &mut CpuBus, (vx, vy): (u8, u8), (gx, gy): (u8, u8), (width, height): (u8, u8)) {
+    for (address, data) in [(0x4000, vx), (0x4001, vy), (0x4002, gx), (0x4003, gy), (0x4004, width), (0x4005, height), (0x4006, 1)] {
+        bus.blitter.write_byte(address, data);
+    }
+
+    blitter.cycle(bus);
+    assert!(blitter.blitting, "blit didn't start");
+    // one cycle per pixel, plus a couple to notice it's finished
+    let limit = (width & 0x7F) as usize * (height & 0x7F) as usize + 2;
+    for _ in 0..limit {
+        if !blitter.blitting {
+            break
+        }
+        blitter.cycle(bus);
+    }
+    assert!(!blitter.blitting, "blit didn't finish in {limit} cycles");
+}
+
+fn render(bus: &CpuBus) -> String {
+    let fb = bus.framebuffers[bus.system_control.banking_register.framebuffer() as usize].borrow();
+    fb.chunks(128)
+        .map(|row| row.iter().map(|&p| pixel_char(p)).collect::<String>() + "\n")
+        .collect()
+}
+
+/// Compares the active framebuffer against `testdata/blitter/{name}.txt`.
+fn check(bus: &CpuBus, name: &str, description: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testdata", "blitter", &format!("{name}.txt")].iter().collect();
+    let actual = render(bus);
+
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::write(&path, format!("# {description}\n{PROVENANCE}\n{actual}")).unwrap();
+        return
+    }
+
+    let reference = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("couldn't read {}: {e}. run with BLESS=1 to create it", path.display()));
+    let expected: Vec<&str> = reference.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(expected.len(), 128, "{} should have 128 rows", path.display());
+
+    for (y, (want, got)) in expected.iter().zip(actual.lines()).enumerate() {
+        if let Some(x) = want.chars().zip(got.chars()).position(|(w, g)| w != g) {
+            let dump = std::env::temp_dir().join(format!("blitter-{name}.txt"));
+            std::fs::write(&dump, &actual).unwrap();
+            panic!(
+                "{name}: first difference at ({x}, {y}), expected {:?} got {:?}\nexpected: {want}\nactual:   {got}\nfull output in {}",
+                want.chars().nth(x).unwrap(), got.chars().nth(x).unwrap(), dump.display()
+            );
+        }
+    }
+}
+
+#[test]
+fn copy() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (8, 8), GLYPH, (16, 16));
+    check(&bus, "copy", "16x16 glyph to (8, 8)");
+}
+
+#[test]
+fn flip_x() {
+    let (mut blitter, mut bus) = setup();
+    // flipped blits read backwards from the inverted source coordinate, so point at the glyph's far edge
+    blit(&mut blitter, &mut bus, (8, 8), (!(GLYPH.0 + 15), GLYPH.1), (16 | 0x80, 16));
+    check(&bus, "flip_x", "16x16 glyph to (8, 8), flipped horizontally");
+}
+
+#[test]
+fn flip_y() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (8, 8), (GLYPH.0, !(GLYPH.1 + 15)), (16, 16 | 0x80));
+    check(&bus, "flip_y", "16x16 glyph to (8, 8), flipped vertically");
+}
+
+#[test]
+fn flip_xy() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (8, 8), (!(GLYPH.0 + 15), !(GLYPH.1 + 15)), (16 | 0x80, 16 | 0x80));
+    check(&bus, "flip_xy", "16x16 glyph to (8, 8), flipped both ways");
+}
+
+#[test]
+fn gcarry_on() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (4, 4), GLYPH, (40, 24));
+    check(&bus, "gcarry_on", "40x24 from the glyph to (4, 4) with gcarry, reading past it into the checkerboard");
+}
+
+#[test]
+fn gcarry_off() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.dma_flags.set_dma_gcarry(false);
+    blit(&mut blitter, &mut bus, (4, 4), GLYPH, (40, 24));
+    check(&bus, "gcarry_off", "40x24 from the glyph to (4, 4) without gcarry, tiling it every 16 pixels");
+}
+
+#[test]
+fn colorfill() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.dma_flags.set_dma_colorfill_enable(true);
+    // the color register is inverted
+    bus.blitter.write_byte(0x4007, !0x0C);
+    blit(&mut blitter, &mut bus, (20, 30), GLYPH, (50, 10));
+    check(&bus, "colorfill", "50x10 of color $0C to (20, 30)");
+}
+
+#[test]
+fn transparent() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (8, 8), GLYPH, (16, 16));
+    blit(&mut blitter, &mut bus, (16, 16), GLYPH, (16, 16));
+    check(&bus, "transparent", "two overlapping glyphs at (8, 8) and (16, 16); color 0 is skipped");
+}
+
+#[test]
+fn opaque() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.dma_flags.set_dma_opaque(true);
+    blit(&mut blitter, &mut bus, (8, 8), GLYPH, (16, 16));
+    blit(&mut blitter, &mut bus, (16, 16), GLYPH, (16, 16));
+    check(&bus, "opaque", "two overlapping glyphs at (8, 8) and (16, 16) with dma_opaque; color 0 is drawn");
+}
+
+#[test]
+fn quadrant_crossing() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (40, 40), (120, 120), (16, 16));
+    check(&bus, "quadrant_crossing", "16x16 from (120, 120) to (40, 40), straddling all four vram quadrants");
+}
+
+#[test]
+fn clip_edges() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.banking_register.set_clip_blits_h(true);
+    bus.system_control.banking_register.set_clip_blits_v(true);
+    blit(&mut blitter, &mut bus, (120, 120), GLYPH, (16, 16));
+    check(&bus, "clip_edges", "16x16 glyph to (120, 120) with both clip bits set; only the top left 8x8 is drawn");
+}
+
+#[test]
+fn clip_h_only() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.banking_register.set_clip_blits_h(true);
+    blit(&mut blitter, &mut bus, (120, 120), GLYPH, (16, 16));
+    check(&bus, "clip_h_only", "16x16 glyph to (120, 120) clipped horizontally; the bottom half wraps to the top");
+}
+
+#[test]
+fn wrap_edges() {
+    let (mut blitter, mut bus) = setup();
+    blit(&mut blitter, &mut bus, (120, 120), GLYPH, (16, 16));
+    check(&bus, "wrap_edges", "16x16 glyph to (120, 120) without clipping; it wraps around into all four corners");
+}
+
+#[test]
+fn second_framebuffer() {
+    let (mut blitter, mut bus) = setup();
+    bus.system_control.banking_register.set_framebuffer(true);
+    blit(&mut blitter, &mut bus, (56, 56), GLYPH, (16, 16));
+    assert!(bus.framebuffers[0].borrow().iter().all(|&p| p == BACKGROUND), "blit drew to the wrong framebuffer");
+    check(&bus, "second_framebuffer", "16x16 glyph to (56, 56) in framebuffer 1");
+}
+
+#[test]
+fn watchpoints_see_blitter_accesses() {
+    let (mut blitter, mut bus) = setup();
+    // the glyph's top left pixel is color 9, and lands at (8, 8)
+    let write = Watchpoint { target: WatchTarget::Framebuffer(0, 8 + 8 * 128), kind: WatchKind::Write, value: Some(9) };
+    bus.watchpoints.list.push(write);
+    blit(&mut blitter, &mut bus, (8, 8), GLYPH, (16, 16));
+    let hit = bus.watchpoints.hit.take().expect("framebuffer watchpoint didn't fire");
+    assert_eq!(hit.watchpoint, write);
+    assert!(hit.blitter);
+
+    let read = Watchpoint { target: WatchTarget::Vram(0, vram_index(GLYPH.0 + 15, GLYPH.1 + 15) as u16), kind: WatchKind::Read, value: None };
+    bus.watchpoints.list = vec![read];
+    blit(&mut blitter, &mut bus, (8, 8), GLYPH, (16, 16));
+    let hit = bus.watchpoints.hit.take().expect("vram watchpoint didn't fire");
+    assert_eq!((hit.watchpoint, hit.data), (read, 0x0E));
+}
@@ -0,0 +1,231 @@
+use std::sync::Arc;
+use tracing::{debug, warn};
+use crate::cartridges::Cartridge;
+
+/// The 2M cart's flash is an Am29F016 compatible part, erased in 64K sectors
+pub const FLASH_SECTOR_SIZE: usize = 0x10000;
+pub const FLASH_SECTORS: usize = 32;
+
+const FLASH_MANUFACTURER_ID: u8 = 0x01;
+const FLASH_DEVICE_ID: u8 = 0xAD;
+
+const SAVE_MAGIC: &[u8; 4] = b"GTSV";
+
+/// Where the flash chip is in a command sequence.
+/// Programs and erases complete instantly, so status polling sees them done on the first read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlashCommand {
+    Read,
+    Unlock1,
+    Unlock2,
+    Program,
+    EraseSetup,
+    EraseUnlock1,
+    EraseUnlock2,
+    Autoselect,
+}
+
+#[derive(Debug, Clone)]
+pub struct Cartridge2M {
+    data: Box<[[u8; 0x4000]; 128]>,
+    /// the flash as it came out of the rom file, for putting sectors back when a save state is loaded
+    rom: Arc<[u8]>,
+    pub bank_shifter: u8,
+    pub bank_mask: u16,
+
+    pub flash_command: FlashCommand,
+    /// sectors that differ from the rom image, and belong in the save file
+    pub modified_sectors: [bool; FLASH_SECTORS],
+    /// set on every program/erase, cleared once the save file has been written
+    pub unsaved: bool,
+    /// set on every program/erase, cleared by the emulator each frame to tell when the game's done writing
+    pub written: bool,
+}
+
+impl Cartridge2M {
+    pub fn reset(&mut self) {
+        self.bank_shifter = 0;
+        self.bank_mask = 0x7E;
+        self.flash_command = FlashCommand::Read;
+    }
+
+    /// The flash as it was in the rom file.
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn flash_address(&self, address: u16) -> usize {
+        let bank = match address {
+            0x4000..=0x7FFF => 0x7F,
+            _ => self.bank_mask as usize & 0x7F,
+        };
+        bank * 0x4000 + (address as usize & 0x3FFF)
+    }
+
+    fn flash_byte(&mut self, flash_address: usize) -> &mut u8 {
+        &mut self.data[flash_address >> 14][flash_address & 0x3FFF]
+    }
+
+    pub fn sector(&self, sector: usize) -> &[u8] {
+        self.data[sector * 4..sector * 4 + 4].as_flattened()
+    }
+
+    fn sector_mut(&mut self, sector: usize) -> &mut [u8] {
+        self.data[sector * 4..sector * 4 + 4].as_flattened_mut()
+    }
+
+    fn erase_sector(&mut self, sector: usize) {
+        debug!("erasing flash sector {}", sector);
+        self.sector_mut(sector).fill(0xFF);
+        self.modified_sectors[sector] = true;
+        self.unsaved = true;
+        self.written = true;
+    }
+
+    pub fn write_byte(&mut self, address: u16, data: u8) {
+        let flash_address = self.flash_address(address);
+        // the chip only decodes the low 11 address lines when checking for unlock cycles
+        let command_address = flash_address & 0x7FF;
+
+        use FlashCommand::*;
+        self.flash_command = match (self.flash_command, command_address, data) {
+            (Program, _, _) => {
+                // programming can only clear bits, that's what erasing is for
+                *self.flash_byte(flash_address) &= data;
+                self.modified_sectors[flash_address / FLASH_SECTOR_SIZE] = true;
+                self.unsaved = true;
+                self.written = true;
+                Read
+            }
+            (_, _, 0xF0) => Read,
+            (Read | Autoselect, 0x555, 0xAA) => Unlock1,
+            (Unlock1, 0x2AA, 0x55) => Unlock2,
+            (Unlock2, 0x555, 0xA0) => Program,
+            (Unlock2, 0x555, 0x80) => EraseSetup,
+            (Unlock2, 0x555, 0x90) => Autoselect,
+            (EraseSetup, 0x555, 0xAA) => EraseUnlock1,
+            (EraseUnlock1, 0x2AA, 0x55) => EraseUnlock2,
+            (EraseUnlock2, _, 0x30) => {
+                self.erase_sector(flash_address / FLASH_SECTOR_SIZE);
+                Read
+            }
+            (EraseUnlock2, 0x555, 0x10) => {
+                for sector in 0..FLASH_SECTORS {
+                    self.erase_sector(sector);
+                }
+                Read
+            }
+            (Autoselect, _, _) => Autoselect,
+            (command, _, _) => {
+                if command != Read {
+                    warn!("Unexpected flash write ${:02X} to ${:06X} during {:?}", data, flash_address, command);
+                }
+                Read
+            }
+        };
+    }
+
+    /// Changes a byte of flash without a program command. The sector counts as modified, so save states
+    /// keep the change, but it isn't a write the game made, so it doesn't need saving to the `.sav`.
+    pub fn poke_byte(&mut self, address: u16, data: u8) {
+        let flash_address = self.flash_address(address);
+        *self.flash_byte(flash_address) = data;
+        self.modified_sectors[flash_address / FLASH_SECTOR_SIZE] = true;
+    }
+
+    /// Overwrites a whole sector, marking it as modified.
+    pub fn load_save_sector(&mut self, sector: usize, data: &[u8]) {
+        self.sector_mut(sector).copy_from_slice(data);
+        self.modified_sectors[sector] = true;
+        self.unsaved = true;
+    }
+
+    /// Puts a sector back the way a save state had it: `Some` with what was written there, or `None` if it
+    /// still matched the rom. Unlike a program or erase, this doesn't need saving to the `.sav`.
+    pub fn restore_sector(&mut self, sector: usize, data: Option<&[u8]>) {
+        match data {
+            Some(data) => {
+                if self.sector(sector) != data {
+                    self.sector_mut(sector).copy_from_slice(data);
+                }
+                self.modified_sectors[sector] = true;
+            }
+            None => {
+                if self.modified_sectors[sector] {
+                    let rom = self.rom.clone();
+                    self.sector_mut(sector).copy_from_slice(&rom[sector * FLASH_SECTOR_SIZE..(sector + 1) * FLASH_SECTOR_SIZE]);
+                }
+                self.modified_sectors[sector] = false;
+            }
+        }
+    }
+
+    /// Serializes every modified sector, for the sidecar save file.
+    pub fn save_data(&self) -> Vec<u8> {
+        let mut bytes = SAVE_MAGIC.to_vec();
+        for sector in (0..FLASH_SECTORS).filter(|s| self.modified_sectors[*s]) {
+            bytes.push(sector as u8);
+            bytes.extend_from_slice(self.sector(sector));
+        }
+        bytes
+    }
+
+    /// Restores sectors written by [`Cartridge2M::save_data`]. Returns false if the data isn't a save file.
+    pub fn load_save_data(&mut self, bytes: &[u8]) -> bool {
+        let Some(mut records) = bytes.strip_prefix(SAVE_MAGIC) else {
+            return false
+        };
+
+        while let [sector, rest @ ..] = records {
+            let sector = *sector as usize;
+            if sector >= FLASH_SECTORS || rest.len() < FLASH_SECTOR_SIZE {
+                return false
+            }
+            self.load_save_sector(sector, &rest[..FLASH_SECTOR_SIZE]);
+            records = &rest[FLASH_SECTOR_SIZE..];
+        }
+        true
+    }
+}
+
+impl Cartridge for Cartridge2M {
+    fn from_slice(slice: &[u8]) -> Self {
+        // built on the heap, 2MiB is more than a test thread's whole stack
+        let banks: Vec<[u8; 0x4000]> = slice.chunks_exact(0x4000).map(|bank| bank.try_into().unwrap()).collect();
+        let data: Box<[[u8; 0x4000]; 128]> = banks.into_boxed_slice().try_into().expect("2M rom should be 2MiB");
+        Self {
+            data,
+            rom: slice.into(),
+            bank_shifter: 0,
+            bank_mask: 0x7E,
+            flash_command: FlashCommand::Read,
+            modified_sectors: [false; FLASH_SECTORS],
+            unsaved: false,
+            written: false,
+        }
+    }
+
+    fn read_byte(&self, address: u16) -> u8 {
+        if self.flash_command == FlashCommand::Autoselect {
+            return match self.flash_address(address) & 0xFF {
+                0x00 => FLASH_MANUFACTURER_ID,
+                0x01 => FLASH_DEVICE_ID,
+                _ => 0x00, // no sectors are write protected
+            }
+        }
+
+        match address {
+            0x4000..=0x7FFF => {
+                self.data[0x7F][address as usize & 0x3FFF]
+            }
+            0x0000..=0x3FFF => {
+                self.data[self.bank_mask as usize & 0x7F][address as usize & 0x3FFF]
+            }
+            _ => { panic!("how the hell did you get here?"); }
+        }
+        // self.data[]
+    }
+}
+
+#[cfg(test)]
+mod tests;
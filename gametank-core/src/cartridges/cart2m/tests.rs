@@ -0,0 +1,123 @@
+//! The flash chip's command sequences, and the `.sav` format built on what they change.
+
+use crate::cartridges::Cartridge;
+use crate::cartridges::cart2m::*;
+
+const ROM_FILL: u8 = 0x5A;
+/// in the fixed bank, which is flash sector 31
+const FIXED: u16 = 0x4000;
+
+fn cart() -> Cartridge2M {
+    Cartridge2M::from_slice(&vec![ROM_FILL; 128 * 0x4000])
+}
+
+/// The unlock cycles before every command. Only the low 11 address lines matter, so any bank does.
+fn command(cart: &mut Cartridge2M, data: u8) {
+    cart.write_byte(FIXED | 0x555, 0xAA);
+    cart.write_byte(FIXED | 0x2AA, 0x55);
+    cart.write_byte(FIXED | 0x555, data);
+}
+
+fn erase(cart: &mut Cartridge2M, address: u16, data: u8) {
+    command(cart, 0x80);
+    cart.write_byte(FIXED | 0x555, 0xAA);
+    cart.write_byte(FIXED | 0x2AA, 0x55);
+    cart.write_byte(address, data);
+}
+
+#[test]
+fn program_only_clears_bits() {
+    let mut cart = cart();
+    cart.write_byte(FIXED | 0x123, 0x00);
+    assert_eq!(cart.read_byte(FIXED | 0x123), ROM_FILL, "a write without a program command changed flash");
+    assert!(!cart.unsaved);
+
+    command(&mut cart, 0xA0);
+    cart.write_byte(FIXED | 0x123, 0x0F);
+    assert_eq!(cart.read_byte(FIXED | 0x123), ROM_FILL & 0x0F);
+    assert_eq!(cart.flash_command, FlashCommand::Read, "programming is one byte at a time");
+    assert!(cart.unsaved && cart.written);
+    assert_eq!(cart.modified_sectors.iter().filter(|&&m| m).count(), 1);
+    assert!(cart.modified_sectors[31]);
+
+    command(&mut cart, 0xA0);
+    cart.write_byte(FIXED | 0x123, 0xF0);
+    assert_eq!(cart.read_byte(FIXED | 0x123), 0x00, "programming set bits that only an erase can");
+}
+
+#[test]
+fn sector_erase_hits_the_addressed_sector() {
+    let mut cart = cart();
+    // bank 4 is the start of sector 1
+    cart.bank_mask = 4;
+    erase(&mut cart, 0x0010, 0x30);
+
+    assert!(cart.sector(1).iter().all(|&b| b == 0xFF));
+    assert!(cart.sector(0).iter().chain(cart.sector(2)).all(|&b| b == ROM_FILL));
+    assert_eq!(cart.modified_sectors.iter().filter(|&&m| m).count(), 1);
+    assert!(cart.modified_sectors[1] && cart.unsaved);
+}
+
+#[test]
+fn chip_erase_needs_the_command_address() {
+    let mut cart = cart();
+    erase(&mut cart, FIXED | 0x123, 0x10);
+    assert_eq!(cart.read_byte(FIXED), ROM_FILL, "chip erase at the wrong address went through");
+    assert!(!cart.unsaved);
+
+    erase(&mut cart, FIXED | 0x555, 0x10);
+    assert!((0..FLASH_SECTORS).all(|s| cart.sector(s).iter().all(|&b| b == 0xFF)));
+    assert!(cart.modified_sectors.iter().all(|&m| m));
+}
+
+#[test]
+fn autoselect_reads_ids_until_reset() {
+    let mut cart = cart();
+    command(&mut cart, 0x90);
+    assert_eq!(cart.read_byte(FIXED), FLASH_MANUFACTURER_ID);
+    assert_eq!(cart.read_byte(FIXED | 0x01), FLASH_DEVICE_ID);
+    assert_eq!(cart.read_byte(FIXED | 0x02), 0x00, "sectors should read as unprotected");
+
+    // anything but a reset or another unlock stays in autoselect
+    cart.write_byte(FIXED, 0x00);
+    assert_eq!(cart.flash_command, FlashCommand::Autoselect);
+    cart.write_byte(FIXED, 0xF0);
+    assert_eq!(cart.read_byte(FIXED), ROM_FILL);
+    assert!(!cart.unsaved);
+}
+
+#[test]
+fn bad_sequences_go_back_to_reading() {
+    let mut cart = cart();
+    cart.write_byte(FIXED | 0x555, 0xAA);
+    cart.write_byte(FIXED | 0x2AA, 0x00);
+    assert_eq!(cart.flash_command, FlashCommand::Read);
+
+    command(&mut cart, 0x80);
+    cart.write_byte(FIXED, 0xF0);
+    assert_eq!(cart.flash_command, FlashCommand::Read, "reset didn't abort the erase");
+}
+
+#[test]
+fn save_data_round_trips() {
+    let mut cart = cart();
+    command(&mut cart, 0xA0);
+    cart.write_byte(FIXED | 0x123, 0x12);
+    cart.bank_mask = 8;
+    erase(&mut cart, 0x0000, 0x30);
+    let save = cart.save_data();
+    assert_eq!(save.len(), 4 + 2 * (1 + FLASH_SECTOR_SIZE), "only the two modified sectors belong in the save");
+
+    let mut loaded = self::cart();
+    assert!(loaded.load_save_data(&save));
+    for sector in 0..FLASH_SECTORS {
+        assert!(loaded.sector(sector) == cart.sector(sector), "sector {sector} differs");
+    }
+    assert_eq!(loaded.modified_sectors, cart.modified_sectors);
+
+    assert!(!self::cart().load_save_data(b"nope"));
+    assert!(!self::cart().load_save_data(&save[..save.len() - 1]), "a truncated save was accepted");
+    let mut bad_sector = save.clone();
+    bad_sector[4] = FLASH_SECTORS as u8;
+    assert!(!self::cart().load_save_data(&bad_sector));
+}
@@ -1,5 +1,5 @@
 use std::ops::{Deref, DerefMut};
-use crate::emulator::cartridges::Cartridge;
+use crate::cartridges::Cartridge;
 
 #[derive(Debug, Clone)]
 pub struct Cartridge32K {
@@ -23,7 +23,7 @@ impl DerefMut for Cartridge32K {
 impl Cartridge for Cartridge32K {
     fn from_slice(slice: &[u8]) -> Self {
         let mut data = [0; 0x8000];
-        data[0x0000..0x8000].copy_from_slice(&slice);
+        data[0x0000..0x8000].copy_from_slice(slice);
         Self {
             data: Box::new(data),
         }
@@ -0,0 +1,154 @@
+pub mod cart8k;
+pub mod cart32k;
+pub mod cart2m;
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use tracing::warn;
+
+use crate::cartridges::cart8k::Cartridge8K;
+use crate::cartridges::cart32k::{Cartridge32K};
+use crate::cartridges::cart2m::Cartridge2M;
+
+pub trait Cartridge {
+    fn from_slice(slice: &[u8]) -> Self;
+    fn read_byte(&self, address: u16) -> u8;
+}
+
+#[derive(Debug, Clone)]
+pub enum CartridgeType {
+    Cart8k(Cartridge8K),
+    Cart32k(Cartridge32K),
+    Cart2m(Box<Cartridge2M>),
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    Io(std::io::Error),
+    /// the image is bigger than any cartridge we know about
+    UnknownSize(usize),
+    /// the image isn't a power of two, so there's no telling where its missing part belongs
+    Truncated(usize),
+    /// the reset vector doesn't point into the cartridge, so the cpu would start executing garbage
+    MissingResetVector(u16),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RomError::Io(e) => write!(f, "couldn't read rom: {e}"),
+            RomError::UnknownSize(len) => write!(f, "rom is {len} bytes, larger than any supported cartridge (2M)"),
+            RomError::Truncated(len) => write!(f, "rom is {len} bytes, which isn't a valid cartridge size; the image may be truncated"),
+            RomError::MissingResetVector(v) => write!(f, "rom has no reset vector (${v:04X} isn't in cartridge space)"),
+        }
+    }
+}
+
+impl Error for RomError {}
+
+impl From<std::io::Error> for RomError {
+    fn from(e: std::io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+impl CartridgeType {
+    /// Picks a cartridge type from the image's size. Power-of-two images that don't match a cartridge exactly
+    /// are mirrored up to the next size that does, like they would be with the upper address lines left unconnected.
+    pub fn from_slice(slice: &[u8]) -> Result<Self, RomError> {
+        let len = slice.len();
+        if len > 0x200000 {
+            return Err(RomError::UnknownSize(len))
+        }
+        if !len.is_power_of_two() || len < 0x10 {
+            return Err(RomError::Truncated(len))
+        }
+
+        let target = match len {
+            0..=0x2000 => 0x2000,
+            0x4000..=0x8000 => 0x8000,
+            _ => 0x200000,
+        };
+        let mirrored;
+        let slice = if len == target {
+            slice
+        } else {
+            warn!("mirroring {} byte rom to fill a {} byte cartridge", len, target);
+            mirrored = slice.repeat(target / len);
+            &mirrored
+        };
+
+        let cartridge = match target {
+            0x2000 => {
+                CartridgeType::Cart8k(Cartridge8K::from_slice(slice))
+            }
+            0x8000 => {
+                CartridgeType::Cart32k(Cartridge32K::from_slice(slice))
+            }
+            _ => {
+                CartridgeType::Cart2m(Box::new(Cartridge2M::from_slice(slice)))
+            }
+        };
+
+        let reset_vector = u16::from_le_bytes([cartridge.read_byte(0x7FFC), cartridge.read_byte(0x7FFD)]);
+        let lowest_address = if target == 0x2000 { 0xE000 } else { 0x8000 };
+        if reset_vector < lowest_address || reset_vector == 0xFFFF {
+            return Err(RomError::MissingResetVector(reset_vector))
+        }
+
+        Ok(cartridge)
+    }
+
+    /// Identifies the rom image the cartridge was made from, whatever's been written to its flash since.
+    pub fn rom_hash(&self) -> u64 {
+        let rom: &[u8] = match self {
+            CartridgeType::Cart8k(c) => &c[..],
+            CartridgeType::Cart32k(c) => &c[..],
+            CartridgeType::Cart2m(c) => c.rom(),
+        };
+        // FNV-1a, because it's stable across rust versions and platforms, unlike `DefaultHasher`
+        rom.iter().fold(0xcbf29ce484222325u64, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    /// Puts any banking hardware on the cartridge back to its power-on state.
+    pub fn reset(&mut self) {
+        match self {
+            CartridgeType::Cart2m(c) => { c.reset(); }
+            _ => {}
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, data: u8) {
+        match self {
+            CartridgeType::Cart2m(c) => { c.write_byte(address, data); }
+            _ => {
+                warn!("Attempted to write read-only memory at: ${:02X}", address as usize + 0x8000);
+            }
+        }
+    }
+
+    /// Patches the rom (or flash) directly, without going through the flash chip's commands.
+    pub fn poke_byte(&mut self, address: u16, data: u8) {
+        match self {
+            CartridgeType::Cart8k(c) => { c[address as usize] = data; }
+            CartridgeType::Cart32k(c) => { c[address as usize] = data; }
+            CartridgeType::Cart2m(c) => { c.poke_byte(address, data); }
+        }
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match self {
+            CartridgeType::Cart8k(c) => {c.read_byte(address)}
+            CartridgeType::Cart32k(c) => {c.read_byte(address)}
+            CartridgeType::Cart2m(c) => {c.read_byte(address)}
+        }
+    }
+}
+
+//
+// fn from_slice(slice: &[u8]) -> Box<dyn Cartridge> {
+//
+// }
+
+#[cfg(test)]
+mod tests;
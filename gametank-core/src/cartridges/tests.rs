@@ -0,0 +1,66 @@
+//! Picking a cartridge from the rom's size, and mirroring small images up to fill it.
+
+use crate::cartridges::{CartridgeType, RomError};
+
+/// A `len` byte image with each 16K bank (or the whole thing, if it's smaller) filled with its own number,
+/// and a reset vector at the very end pointing at `reset`.
+fn image(len: usize, reset: u16) -> Vec<u8> {
+    let mut rom: Vec<u8> = (0..len).map(|i| (i / 0x4000) as u8).collect();
+    rom[len - 4..len - 2].copy_from_slice(&reset.to_le_bytes());
+    rom
+}
+
+fn reset_vector(cartridge: &CartridgeType) -> u16 {
+    u16::from_le_bytes([cartridge.read_byte(0x7FFC), cartridge.read_byte(0x7FFD)])
+}
+
+#[test]
+fn exact_sizes_pick_their_cartridge() {
+    assert!(matches!(CartridgeType::from_slice(&image(0x2000, 0xE000)), Ok(CartridgeType::Cart8k(_))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x8000, 0x8000)), Ok(CartridgeType::Cart32k(_))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x200000, 0xC000)), Ok(CartridgeType::Cart2m(_))));
+}
+
+#[test]
+fn small_images_mirror_up_to_32k() {
+    let cartridge = CartridgeType::from_slice(&image(0x4000, 0xC123)).unwrap();
+    assert!(matches!(cartridge, CartridgeType::Cart32k(_)));
+    assert_eq!(reset_vector(&cartridge), 0xC123);
+    // with A14 unconnected, both halves of the address space see the same 16K
+    for address in [0x0000, 0x1234, 0x3FFB] {
+        assert_eq!(cartridge.read_byte(address), cartridge.read_byte(address + 0x4000), "${address:04X}");
+    }
+
+    let cartridge = CartridgeType::from_slice(&image(0x1000, 0xE010)).unwrap();
+    assert!(matches!(cartridge, CartridgeType::Cart8k(_)));
+    assert_eq!(reset_vector(&cartridge), 0xE010);
+}
+
+#[test]
+fn larger_images_mirror_up_to_2m() {
+    for len in [0x10000, 0x80000, 0x100000] {
+        let rom = image(len, 0xE000);
+        let cartridge = CartridgeType::from_slice(&rom).unwrap();
+        let CartridgeType::Cart2m(cart) = &cartridge else {
+            panic!("{len} byte rom didn't make a 2M cart");
+        };
+        assert_eq!(cart.rom(), rom.repeat(0x200000 / len), "{len} byte rom");
+        // the fixed bank is the last one on the chip, which is the image's own last bank
+        assert_eq!(reset_vector(&cartridge), 0xE000, "{len} byte rom");
+        assert_eq!(cartridge.read_byte(0x4000), ((len - 1) / 0x4000) as u8, "{len} byte rom");
+    }
+}
+
+#[test]
+fn rejects_bad_images() {
+    assert!(matches!(CartridgeType::from_slice(&image(0x400000, 0xE000)), Err(RomError::UnknownSize(0x400000))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x200001, 0xE000)), Err(RomError::UnknownSize(0x200001))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x7FFF, 0xE000)), Err(RomError::Truncated(0x7FFF))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x18000, 0xE000)), Err(RomError::Truncated(0x18000))));
+    assert!(matches!(CartridgeType::from_slice(&[]), Err(RomError::Truncated(0))));
+
+    // the vector has to point into the cartridge, and an erased one is all $FF
+    assert!(matches!(CartridgeType::from_slice(&image(0x2000, 0xC000)), Err(RomError::MissingResetVector(0xC000))));
+    assert!(matches!(CartridgeType::from_slice(&image(0x8000, 0x7FFF)), Err(RomError::MissingResetVector(0x7FFF))));
+    assert!(matches!(CartridgeType::from_slice(&vec![0xFF; 0x8000]), Err(RomError::MissingResetVector(0xFFFF))));
+}
@@ -0,0 +1,301 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::warn;
+use w65c02s::State;
+use crate::disassembler::{disassemble, Instruction};
+use crate::cartridges::CartridgeType;
+use crate::emulator::{Emulator, PlayState};
+use crate::symbols::{SymbolError, SymbolTable};
+use crate::watchpoint::{WatchHit, WatchTarget, Watchpoint};
+
+const OPCODE_BRK: u8 = 0x00;
+const OPCODE_JSR: u8 = 0x20;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Processor {
+    Cpu,
+    Acp,
+}
+
+impl Display for Processor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Processor::Cpu => write!(f, "cpu"),
+            Processor::Acp => write!(f, "acp"),
+        }
+    }
+}
+
+impl FromStr for Processor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpu" => Ok(Processor::Cpu),
+            "acp" => Ok(Processor::Acp),
+            _ => Err(format!("unknown processor {s:?}, expected cpu or acp")),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Breakpoint {
+    pub processor: Processor,
+    pub address: u16,
+    /// only break while this 2M cart bank is mapped in
+    pub bank: Option<u8>,
+}
+
+impl Breakpoint {
+    pub fn new(processor: Processor, address: u16) -> Self {
+        Self { processor, address, bank: None }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ${:04X}", self.processor, self.address)?;
+        if let Some(bank) = self.bank {
+            write!(f, " (bank {bank:02X})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Why emulation stopped. The PC is always that of the next instruction, which hasn't run yet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HaltReason {
+    Breakpoint(Processor, u16),
+    Brk(Processor, u16),
+    Step(Processor, u16),
+    RunToCursor(Processor, u16),
+    /// stopped from outside, e.g. by a gdb client
+    Interrupted(Processor, u16),
+    /// the instruction that made the access has already finished
+    Watchpoint(Processor, Instruction, WatchHit),
+}
+
+impl Display for HaltReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HaltReason::Breakpoint(p, pc) => write!(f, "{p} hit breakpoint at ${pc:04X}"),
+            HaltReason::Brk(p, pc) => write!(f, "{p} about to BRK at ${pc:04X}"),
+            HaltReason::Step(p, pc) => write!(f, "{p} stepped to ${pc:04X}"),
+            HaltReason::RunToCursor(p, pc) => write!(f, "{p} ran to ${pc:04X}"),
+            HaltReason::Interrupted(p, pc) => write!(f, "{p} interrupted at ${pc:04X}"),
+            HaltReason::Watchpoint(p, instruction, hit) if hit.blitter => {
+                write!(f, "{hit} while the {p} ran ${:04X}: {instruction}", instruction.address)
+            }
+            HaltReason::Watchpoint(p, instruction, hit) => {
+                write!(f, "{p} {hit} at ${:04X}: {instruction}", instruction.address)
+            }
+        }
+    }
+}
+
+/// Execution breakpoints and stepping for both processors.
+/// Checked before every instruction, so emulation can stop anywhere in a frame.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    pub breakpoints: BTreeSet<Breakpoint>,
+    pub pause_on_brk: bool,
+    pub halted: Option<HaltReason>,
+    /// for the cpu's program; the acp's code is usually uploaded from somewhere in there too, but is assembled separately
+    pub symbols: SymbolTable,
+
+    run_to: Option<(Processor, u16)>,
+    step: Option<Processor>,
+    /// where each processor was when we resumed, so we don't immediately stop at the same spot again
+    resumed_at: [Option<u16>; 2],
+}
+
+impl Debugger {
+    pub fn is_halted(&self) -> bool {
+        self.halted.is_some()
+    }
+
+    pub fn toggle_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.remove(&breakpoint) {
+            self.breakpoints.insert(breakpoint);
+        }
+    }
+}
+
+impl Emulator {
+    pub fn pc(&self, processor: Processor) -> u16 {
+        match processor {
+            Processor::Cpu => self.cpu.get_pc(),
+            Processor::Acp => self.acp.get_pc(),
+        }
+    }
+
+    /// Reads memory as the given processor sees it, without any side effects.
+    pub fn peek(&self, processor: Processor, address: u16) -> u8 {
+        match processor {
+            Processor::Cpu => self.cpu_bus.peek_byte(address),
+            Processor::Acp => {
+                // aram only lives on the acp bus while the acp is running
+                let aram = self.acp_bus.aram.as_ref().or(self.cpu_bus.aram.as_ref());
+                aram.map_or(0, |aram| aram[address as usize % 0x1000])
+            }
+        }
+    }
+
+    /// The 2M cart bank behind a cpu address, if it's in the switchable window.
+    pub fn cpu_bank(&self, address: u16) -> Option<u8> {
+        match (&self.cpu_bus.cartridge, address) {
+            (CartridgeType::Cart2m(cart), 0x8000..=0xBFFF) => Some((cart.bank_mask & 0x7F) as u8),
+            _ => None,
+        }
+    }
+
+    pub fn load_symbols(&mut self, path: &Path) -> Result<(), SymbolError> {
+        self.debugger.symbols = SymbolTable::load(path)?;
+        warn!(" - loaded {} symbols from {}", self.debugger.symbols.symbols().len(), path.display());
+        Ok(())
+    }
+
+    /// The name of whatever's at `address` right now, as `processor` sees it.
+    pub fn symbol_at(&self, processor: Processor, address: u16) -> Option<&str> {
+        match processor {
+            Processor::Cpu => self.debugger.symbols.label(address, self.cpu_bank(address)),
+            Processor::Acp => None,
+        }
+    }
+
+    pub fn source_line(&self, processor: Processor, address: u16) -> Option<(&str, u32)> {
+        match processor {
+            Processor::Cpu => self.debugger.symbols.source_line(address, self.cpu_bank(address)),
+            Processor::Acp => None,
+        }
+    }
+
+    /// A cpu breakpoint on a symbol, restricted to its bank if it's banked code.
+    pub fn symbol_breakpoint(&self, name: &str) -> Option<Breakpoint> {
+        let symbol = self.debugger.symbols.find(name)?;
+        Some(Breakpoint { processor: Processor::Cpu, address: symbol.address, bank: symbol.bank })
+    }
+
+    fn breakpoint_hit(&self, processor: Processor, pc: u16) -> bool {
+        let bank = match processor {
+            Processor::Cpu => self.cpu_bank(pc),
+            Processor::Acp => None,
+        };
+        let first = Breakpoint::new(processor, pc);
+        let last = Breakpoint { bank: Some(u8::MAX), ..first };
+        self.debugger.breakpoints.range(first..=last)
+            .any(|b| b.bank.is_none() || b.bank == bank)
+    }
+
+    fn halt(&mut self, reason: HaltReason) {
+        warn!("halted: {reason}");
+        self.debugger.halted = Some(reason);
+        self.play_state = PlayState::Paused;
+    }
+
+    /// Stops the cpu before its next instruction.
+    pub fn interrupt(&mut self) {
+        self.halt(HaltReason::Interrupted(Processor::Cpu, self.cpu.get_pc()));
+    }
+
+    /// Picks up where a halt (or pause) left off.
+    pub fn resume(&mut self) {
+        self.debugger.halted = None;
+        self.debugger.resumed_at = [Some(self.cpu.get_pc()), Some(self.acp.get_pc())];
+        self.play_state = PlayState::Playing;
+    }
+
+    /// Runs exactly one instruction on `processor`, and whatever the rest of the board does meanwhile.
+    pub fn step_into(&mut self, processor: Processor) {
+        self.debugger.step = Some(processor);
+        self.resume();
+    }
+
+    /// Like [`Emulator::step_into`], but runs subroutine calls to completion.
+    pub fn step_over(&mut self, processor: Processor) {
+        let pc = self.pc(processor);
+        if self.peek(processor, pc) == OPCODE_JSR {
+            self.run_to_cursor(processor, pc.wrapping_add(3));
+        } else {
+            self.step_into(processor);
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.cpu_bus.watchpoints.list
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if self.cpu_bus.watchpoints.list.contains(&watchpoint) {
+            return
+        }
+        self.cpu_bus.watchpoints.list.push(watchpoint);
+        // the acp can only see aram
+        if let WatchTarget::Aram(_) = watchpoint.target {
+            self.acp_bus.watchpoints.list.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) {
+        self.cpu_bus.watchpoints.list.retain(|w| w != watchpoint);
+        self.acp_bus.watchpoints.list.retain(|w| w != watchpoint);
+    }
+
+    pub fn run_to_cursor(&mut self, processor: Processor, address: u16) {
+        self.debugger.run_to = Some((processor, address));
+        self.resume();
+    }
+
+    /// Called before `processor` executes an instruction. Returns true if emulation should halt instead.
+    pub(crate) fn check_breakpoints(&mut self, processor: Processor) -> bool {
+        let state = match processor {
+            Processor::Cpu => self.cpu.get_state(),
+            Processor::Acp => self.acp.get_state(),
+        };
+        // a waiting cpu sits on the same pc for ages, and isn't about to execute anything
+        if state != State::Running {
+            return false
+        }
+
+        let pc = self.pc(processor);
+        if self.debugger.resumed_at[processor as usize].take() == Some(pc) {
+            return false
+        }
+
+        let reason = if self.debugger.run_to == Some((processor, pc)) {
+            self.debugger.run_to = None;
+            HaltReason::RunToCursor(processor, pc)
+        } else if self.breakpoint_hit(processor, pc) {
+            HaltReason::Breakpoint(processor, pc)
+        } else if self.debugger.pause_on_brk && self.peek(processor, pc) == OPCODE_BRK {
+            HaltReason::Brk(processor, pc)
+        } else {
+            return false
+        };
+
+        self.halt(reason);
+        true
+    }
+
+    /// Called after `processor` executes the instruction at `pc`, halting if it touched a watchpoint.
+    pub(crate) fn check_watchpoints(&mut self, processor: Processor, pc: u16) {
+        let hit = match processor {
+            Processor::Cpu => self.cpu_bus.watchpoints.hit.take(),
+            Processor::Acp => self.acp_bus.watchpoints.hit.take(),
+        };
+        if let Some(hit) = hit {
+            let instruction = disassemble(pc, |address| self.peek(processor, address));
+            self.halt(HaltReason::Watchpoint(processor, instruction, hit));
+        }
+    }
+
+    /// Called after `processor` executes an instruction, to finish off a single step.
+    pub(crate) fn check_step(&mut self, processor: Processor) {
+        if self.debugger.step == Some(processor) {
+            self.debugger.step = None;
+            self.halt(HaltReason::Step(processor, self.pc(processor)));
+        }
+    }
+}
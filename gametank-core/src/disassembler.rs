@@ -0,0 +1,256 @@
+use std::fmt::{Display, Formatter};
+use AddressingMode::*;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressingMode {
+    /// `INX`, `RTS`, ...
+    Implied,
+    /// `ASL A`
+    Accumulator,
+    /// `LDA #$12`
+    Immediate,
+    /// `LDA $12`
+    ZeroPage,
+    /// `LDA $12,X`
+    ZeroPageX,
+    /// `LDX $12,Y`
+    ZeroPageY,
+    /// `LDA ($12)`
+    ZeroPageIndirect,
+    /// `LDA ($12,X)`
+    ZeroPageIndexedIndirect,
+    /// `LDA ($12),Y`
+    ZeroPageIndirectIndexed,
+    /// `LDA $1234`
+    Absolute,
+    /// `LDA $1234,X`
+    AbsoluteX,
+    /// `LDA $1234,Y`
+    AbsoluteY,
+    /// `JMP ($1234)`
+    AbsoluteIndirect,
+    /// `JMP ($1234,X)`
+    AbsoluteIndexedIndirect,
+    /// `BNE label`
+    Relative,
+    /// `BBR0 $12,label`
+    ZeroPageRelative,
+}
+
+impl AddressingMode {
+    /// Instruction length in bytes, including the opcode.
+    pub fn size(self) -> u16 {
+        match self {
+            Implied | Accumulator => 1,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | ZeroPageIndirect | ZeroPageIndexedIndirect
+            | ZeroPageIndirectIndexed | Relative => 2,
+            Absolute | AbsoluteX | AbsoluteY | AbsoluteIndirect | AbsoluteIndexedIndirect | ZeroPageRelative => 3,
+        }
+    }
+}
+
+type Ins = (&'static str, AddressingMode);
+
+// the unused opcodes are all NOPs of various lengths on the 65C02
+#[rustfmt::skip]
+static OPCODES: [Ins; 256] = [
+    // 0x00
+    ("BRK", Implied), ("ORA", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("TSB", ZeroPage), ("ORA", ZeroPage), ("ASL", ZeroPage), ("RMB0", ZeroPage),
+    ("PHP", Implied), ("ORA", Immediate), ("ASL", Accumulator), ("NOP", Implied),
+    ("TSB", Absolute), ("ORA", Absolute), ("ASL", Absolute), ("BBR0", ZeroPageRelative),
+    // 0x10
+    ("BPL", Relative), ("ORA", ZeroPageIndirectIndexed), ("ORA", ZeroPageIndirect), ("NOP", Implied),
+    ("TRB", ZeroPage), ("ORA", ZeroPageX), ("ASL", ZeroPageX), ("RMB1", ZeroPage),
+    ("CLC", Implied), ("ORA", AbsoluteY), ("INC", Accumulator), ("NOP", Implied),
+    ("TRB", Absolute), ("ORA", AbsoluteX), ("ASL", AbsoluteX), ("BBR1", ZeroPageRelative),
+    // 0x20
+    ("JSR", Absolute), ("AND", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("BIT", ZeroPage), ("AND", ZeroPage), ("ROL", ZeroPage), ("RMB2", ZeroPage),
+    ("PLP", Implied), ("AND", Immediate), ("ROL", Accumulator), ("NOP", Implied),
+    ("BIT", Absolute), ("AND", Absolute), ("ROL", Absolute), ("BBR2", ZeroPageRelative),
+    // 0x30
+    ("BMI", Relative), ("AND", ZeroPageIndirectIndexed), ("AND", ZeroPageIndirect), ("NOP", Implied),
+    ("BIT", ZeroPageX), ("AND", ZeroPageX), ("ROL", ZeroPageX), ("RMB3", ZeroPage),
+    ("SEC", Implied), ("AND", AbsoluteY), ("DEC", Accumulator), ("NOP", Implied),
+    ("BIT", AbsoluteX), ("AND", AbsoluteX), ("ROL", AbsoluteX), ("BBR3", ZeroPageRelative),
+    // 0x40
+    ("RTI", Implied), ("EOR", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("NOP", ZeroPage), ("EOR", ZeroPage), ("LSR", ZeroPage), ("RMB4", ZeroPage),
+    ("PHA", Implied), ("EOR", Immediate), ("LSR", Accumulator), ("NOP", Implied),
+    ("JMP", Absolute), ("EOR", Absolute), ("LSR", Absolute), ("BBR4", ZeroPageRelative),
+    // 0x50
+    ("BVC", Relative), ("EOR", ZeroPageIndirectIndexed), ("EOR", ZeroPageIndirect), ("NOP", Implied),
+    ("NOP", ZeroPageX), ("EOR", ZeroPageX), ("LSR", ZeroPageX), ("RMB5", ZeroPage),
+    ("CLI", Implied), ("EOR", AbsoluteY), ("PHY", Implied), ("NOP", Implied),
+    ("NOP", Absolute), ("EOR", AbsoluteX), ("LSR", AbsoluteX), ("BBR5", ZeroPageRelative),
+    // 0x60
+    ("RTS", Implied), ("ADC", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("STZ", ZeroPage), ("ADC", ZeroPage), ("ROR", ZeroPage), ("RMB6", ZeroPage),
+    ("PLA", Implied), ("ADC", Immediate), ("ROR", Accumulator), ("NOP", Implied),
+    ("JMP", AbsoluteIndirect), ("ADC", Absolute), ("ROR", Absolute), ("BBR6", ZeroPageRelative),
+    // 0x70
+    ("BVS", Relative), ("ADC", ZeroPageIndirectIndexed), ("ADC", ZeroPageIndirect), ("NOP", Implied),
+    ("STZ", ZeroPageX), ("ADC", ZeroPageX), ("ROR", ZeroPageX), ("RMB7", ZeroPage),
+    ("SEI", Implied), ("ADC", AbsoluteY), ("PLY", Implied), ("NOP", Implied),
+    ("JMP", AbsoluteIndexedIndirect), ("ADC", AbsoluteX), ("ROR", AbsoluteX), ("BBR7", ZeroPageRelative),
+    // 0x80
+    ("BRA", Relative), ("STA", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("STY", ZeroPage), ("STA", ZeroPage), ("STX", ZeroPage), ("SMB0", ZeroPage),
+    ("DEY", Implied), ("BIT", Immediate), ("TXA", Implied), ("NOP", Implied),
+    ("STY", Absolute), ("STA", Absolute), ("STX", Absolute), ("BBS0", ZeroPageRelative),
+    // 0x90
+    ("BCC", Relative), ("STA", ZeroPageIndirectIndexed), ("STA", ZeroPageIndirect), ("NOP", Implied),
+    ("STY", ZeroPageX), ("STA", ZeroPageX), ("STX", ZeroPageY), ("SMB1", ZeroPage),
+    ("TYA", Implied), ("STA", AbsoluteY), ("TXS", Implied), ("NOP", Implied),
+    ("STZ", Absolute), ("STA", AbsoluteX), ("STZ", AbsoluteX), ("BBS1", ZeroPageRelative),
+    // 0xA0
+    ("LDY", Immediate), ("LDA", ZeroPageIndexedIndirect), ("LDX", Immediate), ("NOP", Implied),
+    ("LDY", ZeroPage), ("LDA", ZeroPage), ("LDX", ZeroPage), ("SMB2", ZeroPage),
+    ("TAY", Implied), ("LDA", Immediate), ("TAX", Implied), ("NOP", Implied),
+    ("LDY", Absolute), ("LDA", Absolute), ("LDX", Absolute), ("BBS2", ZeroPageRelative),
+    // 0xB0
+    ("BCS", Relative), ("LDA", ZeroPageIndirectIndexed), ("LDA", ZeroPageIndirect), ("NOP", Implied),
+    ("LDY", ZeroPageX), ("LDA", ZeroPageX), ("LDX", ZeroPageY), ("SMB3", ZeroPage),
+    ("CLV", Implied), ("LDA", AbsoluteY), ("TSX", Implied), ("NOP", Implied),
+    ("LDY", AbsoluteX), ("LDA", AbsoluteX), ("LDX", AbsoluteY), ("BBS3", ZeroPageRelative),
+    // 0xC0
+    ("CPY", Immediate), ("CMP", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("CPY", ZeroPage), ("CMP", ZeroPage), ("DEC", ZeroPage), ("SMB4", ZeroPage),
+    ("INY", Implied), ("CMP", Immediate), ("DEX", Implied), ("WAI", Implied),
+    ("CPY", Absolute), ("CMP", Absolute), ("DEC", Absolute), ("BBS4", ZeroPageRelative),
+    // 0xD0
+    ("BNE", Relative), ("CMP", ZeroPageIndirectIndexed), ("CMP", ZeroPageIndirect), ("NOP", Implied),
+    ("NOP", ZeroPageX), ("CMP", ZeroPageX), ("DEC", ZeroPageX), ("SMB5", ZeroPage),
+    ("CLD", Implied), ("CMP", AbsoluteY), ("PHX", Implied), ("STP", Implied),
+    ("NOP", Absolute), ("CMP", AbsoluteX), ("DEC", AbsoluteX), ("BBS5", ZeroPageRelative),
+    // 0xE0
+    ("CPX", Immediate), ("SBC", ZeroPageIndexedIndirect), ("NOP", Immediate), ("NOP", Implied),
+    ("CPX", ZeroPage), ("SBC", ZeroPage), ("INC", ZeroPage), ("SMB6", ZeroPage),
+    ("INX", Implied), ("SBC", Immediate), ("NOP", Implied), ("NOP", Implied),
+    ("CPX", Absolute), ("SBC", Absolute), ("INC", Absolute), ("BBS6", ZeroPageRelative),
+    // 0xF0
+    ("BEQ", Relative), ("SBC", ZeroPageIndirectIndexed), ("SBC", ZeroPageIndirect), ("NOP", Implied),
+    ("NOP", ZeroPageX), ("SBC", ZeroPageX), ("INC", ZeroPageX), ("SMB7", ZeroPage),
+    ("SED", Implied), ("SBC", AbsoluteY), ("PLX", Implied), ("NOP", Implied),
+    ("NOP", Absolute), ("SBC", AbsoluteX), ("INC", AbsoluteX), ("BBS7", ZeroPageRelative),
+];
+
+/// A single decoded instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: [u8; 3],
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    pub fn size(&self) -> u16 {
+        self.mode.size()
+    }
+
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.size())
+    }
+
+    fn byte_operand(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    fn word_operand(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+
+    /// Where a branch or jump goes, if it goes somewhere fixed.
+    pub fn target(&self) -> Option<u16> {
+        match (self.mode, self.mnemonic) {
+            (Relative, _) => Some(self.next_address().wrapping_add(self.bytes[1] as i8 as u16)),
+            (ZeroPageRelative, _) => Some(self.next_address().wrapping_add(self.bytes[2] as i8 as u16)),
+            (Absolute, "JMP" | "JSR") => Some(self.word_operand()),
+            _ => None,
+        }
+    }
+
+    /// The operand as it would be written in assembly, with `label` used to name addresses.
+    pub fn operand_with(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        let zp = |v: u8| label(v as u16).unwrap_or_else(|| format!("${v:02X}"));
+        let abs = |v: u16| label(v).unwrap_or_else(|| format!("${v:04X}"));
+
+        match self.mode {
+            Implied => String::new(),
+            Accumulator => "A".into(),
+            Immediate => format!("#${:02X}", self.byte_operand()),
+            ZeroPage => zp(self.byte_operand()),
+            ZeroPageX => format!("{},X", zp(self.byte_operand())),
+            ZeroPageY => format!("{},Y", zp(self.byte_operand())),
+            ZeroPageIndirect => format!("({})", zp(self.byte_operand())),
+            ZeroPageIndexedIndirect => format!("({},X)", zp(self.byte_operand())),
+            ZeroPageIndirectIndexed => format!("({}),Y", zp(self.byte_operand())),
+            Absolute => abs(self.word_operand()),
+            AbsoluteX => format!("{},X", abs(self.word_operand())),
+            AbsoluteY => format!("{},Y", abs(self.word_operand())),
+            AbsoluteIndirect => format!("({})", abs(self.word_operand())),
+            AbsoluteIndexedIndirect => format!("({},X)", abs(self.word_operand())),
+            Relative => abs(self.target().unwrap_or_default()),
+            ZeroPageRelative => format!("{},{}", zp(self.byte_operand()), abs(self.target().unwrap_or_default())),
+        }
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let operand = self.operand_with(|_| None);
+        if operand.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, operand)
+        }
+    }
+}
+
+/// Decodes the instruction at `address`. `peek` must be free of side effects,
+/// since the disassembler reads ahead of wherever the cpu actually is.
+pub fn disassemble(address: u16, peek: impl Fn(u16) -> u8) -> Instruction {
+    let opcode = peek(address);
+    let (mnemonic, mode) = OPCODES[opcode as usize];
+    let mut bytes = [opcode, 0, 0];
+    for i in 1..mode.size() {
+        bytes[i as usize] = peek(address.wrapping_add(i));
+    }
+    Instruction { address, bytes, mnemonic, mode }
+}
+
+/// Disassembles `count` instructions in a row, starting at `address`.
+pub fn disassemble_range(address: u16, count: usize, peek: impl Fn(u16) -> u8) -> Vec<Instruction> {
+    let mut instructions = Vec::with_capacity(count);
+    let mut address = address;
+    for _ in 0..count {
+        let instruction = disassemble(address, &peek);
+        address = instruction.next_address();
+        instructions.push(instruction);
+    }
+    instructions
+}
+
+/// Finds somewhere up to `lookback` bytes before `address` that disassembles cleanly into it,
+/// so there's some context before the pc. Falls back to `address` itself.
+pub fn find_start(address: u16, lookback: u16, peek: impl Fn(u16) -> u8) -> u16 {
+    for back in (1..=lookback).rev() {
+        let mut candidate = address.wrapping_sub(back);
+        while candidate != address && address.wrapping_sub(candidate) <= back {
+            candidate = disassemble(candidate, &peek).next_address();
+        }
+        if candidate == address {
+            return address.wrapping_sub(back)
+        }
+    }
+    address
+}
+
+#[cfg(test)]
+mod tests;
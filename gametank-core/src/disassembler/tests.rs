@@ -0,0 +1,74 @@
+//! Decoding the 65C02-only instructions and the undocumented NOPs, and finding somewhere to start.
+
+use crate::disassembler::*;
+
+/// Peeks `bytes` as if they were at `base`, with $EA (NOP) everywhere else.
+fn memory(base: u16, bytes: &[u8]) -> impl Fn(u16) -> u8 + '_ {
+    move |address| bytes.get(address.wrapping_sub(base) as usize).copied().unwrap_or(0xEA)
+}
+
+#[test]
+fn decodes_65c02_instructions() {
+    // bytes at $C000, how they should print, and their length
+    let table: &[(&[u8], &str, u16)] = &[
+        (&[0xCB], "WAI", 1),
+        (&[0xDB], "STP", 1),
+        (&[0x07, 0x12], "RMB0 $12", 2),
+        (&[0x77, 0x34], "RMB7 $34", 2),
+        (&[0x87, 0x12], "SMB0 $12", 2),
+        (&[0xF7, 0xFF], "SMB7 $FF", 2),
+        // branches are relative to the end of the instruction
+        (&[0x0F, 0x12, 0x05], "BBR0 $12,$C008", 3),
+        (&[0x7F, 0x12, 0xFD], "BBR7 $12,$C000", 3),
+        (&[0x8F, 0x80, 0x00], "BBS0 $80,$C003", 3),
+        (&[0xFF, 0x80, 0x80], "BBS7 $80,$BF83", 3),
+        (&[0x80, 0xFE], "BRA $C000", 2),
+        (&[0x7C, 0x34, 0x12], "JMP ($1234,X)", 3),
+        (&[0xB2, 0x12], "LDA ($12)", 2),
+    ];
+    for &(bytes, text, size) in table {
+        let instruction = disassemble(0xC000, memory(0xC000, bytes));
+        assert_eq!((instruction.to_string().as_str(), instruction.size()), (text, size), "{bytes:02X?}");
+    }
+
+    let bbs = disassemble(0xC000, memory(0xC000, &[0xFF, 0x80, 0x80]));
+    assert_eq!(bbs.target(), Some(0xBF83));
+    assert_eq!(bbs.next_address(), 0xC003);
+}
+
+#[test]
+fn undocumented_nops_skip_their_operands() {
+    // the unused opcodes are NOPs that still fetch operands, so the next instruction is after them
+    let lengths = [
+        (&[0x02, 0x22, 0x42, 0x62, 0x82, 0xC2, 0xE2][..], 2),
+        (&[0x44, 0x54, 0xD4, 0xF4], 2),
+        (&[0x5C, 0xDC, 0xFC], 3),
+        (&[0x03, 0x13, 0x33, 0x73, 0xB3, 0xF3, 0x0B, 0x1B, 0x5B, 0xBB, 0xEB, 0xFB], 1),
+    ];
+    for (opcodes, size) in lengths {
+        for &opcode in opcodes {
+            let instruction = disassemble(0x8000, memory(0x8000, &[opcode, 0x12, 0x34]));
+            assert_eq!((instruction.mnemonic, instruction.size()), ("NOP", size), "${opcode:02X}");
+        }
+    }
+
+    let listing: Vec<_> = disassemble_range(0x8000, 3, memory(0x8000, &[0x5C, 0x12, 0x34, 0x02, 0x56, 0xE8]))
+        .iter().map(|i| i.to_string()).collect();
+    assert_eq!(listing, ["NOP $3412", "NOP #$56", "INX"]);
+}
+
+#[test]
+fn find_start_resyncs_onto_the_address() {
+    // JSR's operand would run past $1003, and so would the BITs that `LDA $2C2C`'s operand reads as,
+    // so the start has to be the LDA itself
+    let bytes = [0x20, 0xAD, 0x2C, 0x2C, 0xE8];
+    let peek = memory(0x0FFF, &bytes);
+    assert_eq!(find_start(0x1003, 4, &peek), 0x1000);
+    assert_eq!(find_start(0x1003, 2, &peek), 0x1003, "nothing within the lookback lands on it");
+
+    // when several starts work, the furthest back wins
+    let nops = memory(0x2000, &[]);
+    assert_eq!(find_start(0x2010, 8, &nops), 0x2008);
+    // and it copes with wrapping around the bottom of memory
+    assert_eq!(find_start(0x0002, 4, &nops), 0xFFFE);
+}
@@ -0,0 +1,614 @@
+use w65c02s::{System, W65C02S};
+use std::collections::HashMap;
+use tracing::{debug, warn};
+use w65c02s::State::AwaitingInterrupt;
+use std::fmt::{Debug, Formatter};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use crate::blitter::Blitter;
+use crate::cartridges::{CartridgeType, RomError};
+use crate::gametank_bus::{AcpBus, Bus, CpuBus};
+use crate::helpers::get_now_ms;
+use crate::input::ControllerButton::{Down, Left, Right, Start, Up, A, B, C};
+use crate::input::{ControllerButton, InputCommand, KeyState};
+use crate::input::InputCommand::{Controller1, Controller2, HardReset, PlayPause, QuickLoad, QuickSave, Rewind, SoftReset};
+use crate::input::KeyState::JustReleased;
+use crate::emulator::PlayState::{Paused, Playing, WasmInit};
+use crate::debugger::{Debugger, Processor};
+use crate::trace::Tracer;
+use crate::audio_capture::AudioCapture;
+use crate::audio_output::AudioOutput;
+use crate::rewind::RewindBuffer;
+use crate::savestate::SaveStateError;
+
+pub const WIDTH: u32 = 128;
+pub const HEIGHT: u32 = 128;
+
+/// CPU cycles between vblanks
+pub const CYCLES_PER_FRAME: i32 = 59659;
+
+/// Number of in-memory quick-save slots
+pub const SAVE_SLOTS: usize = 4;
+/// how long flash has to go unwritten before the `.sav` is updated, so a game saving over several frames is one write
+pub const SAVE_FLUSH_DELAY_FRAMES: u32 = 30;
+
+/// What the cpu sees while it executes an instruction. The blitter is clocked after every bus access,
+/// so its reads and writes interleave with the cpu's cycle by cycle, like on the console.
+///
+/// Who gets graphics memory is all down to `dma_enable`, which the blitter checks every cycle:
+/// - set: $4000-$7FFF is the blitter's registers as far as the cpu's concerned, so it can't touch
+///   vram or the framebuffers at all (see `SystemControl::get_graphics_memory_map`)
+/// - clear: the cpu gets vram or a framebuffer there, and a running blit keeps counting
+///   without writing anything (see `Blitter::cycle`)
+///
+/// Neither side ever waits on the other, since they never share memory at the same time.
+struct Board<'a> {
+    bus: &'a mut CpuBus,
+    blitter: &'a mut Blitter,
+    instant_blit: bool,
+}
+
+impl Board<'_> {
+    fn clock_blitter(&mut self, cpu: &mut W65C02S) {
+        if self.instant_blit {
+            self.blitter.instant_blit(self.bus);
+        } else {
+            self.blitter.cycle(self.bus);
+        }
+        // the blitter and via share the cpu's irq line
+        cpu.set_irq(self.blitter.irq_trigger || self.bus.system_control.via.irq());
+    }
+}
+
+impl System for Board<'_> {
+    fn read(&mut self, cpu: &mut W65C02S, addr: u16) -> u8 {
+        let data = self.bus.read(cpu, addr);
+        self.clock_blitter(cpu);
+        data
+    }
+
+    fn write(&mut self, cpu: &mut W65C02S, addr: u16, data: u8) {
+        self.bus.write(cpu, addr, data);
+        self.clock_blitter(cpu);
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PlayState {
+    WasmInit,
+    Paused,
+    Playing,
+}
+
+pub struct Emulator {
+    pub cpu_bus: CpuBus,
+    pub acp_bus: AcpBus,
+    pub cpu: W65C02S,
+    pub acp: W65C02S,
+
+    pub blitter: Blitter,
+    /// finish every blit on the cycle it starts, for fast-forwarding or ruling out blit timing bugs
+    pub instant_blit: bool,
+
+    pub clock_cycles_to_vblank: i32,
+    pub acp_cycle_accumulator: i32,
+    pub frame_count: u64,
+
+    pub last_emu_tick: f64,
+    pub cpu_ns_per_cycle: f64,
+    pub cpu_frequency_hz: f64,
+    pub last_render_time: f64,
+    pub audio_out: Option<Box<dyn AudioOutput>>,
+    /// keep the audio buffer topped up instead of just following the wall clock, see [`crate::pacing`]
+    pub audio_pacing: bool,
+    pub play_state: PlayState,
+    pub wait_counter: u64,
+
+    pub save_slots: [Option<Vec<u8>>; SAVE_SLOTS],
+    pub rewind: RewindBuffer,
+    pub rewinding: bool,
+    pub rewind_accumulator: i32,
+
+    pub debugger: Debugger,
+    pub tracer: Option<Tracer>,
+    pub audio_capture: Option<AudioCapture>,
+
+    /// where the cartridge's flash gets persisted, if it came from a file. Quick save slots go next to it.
+    pub save_path: Option<PathBuf>,
+    /// so save states can tell which game they belong to
+    pub rom_hash: u64,
+    /// frames since the cartridge's flash was last written
+    pub frames_since_flash_write: u32,
+
+    pub input_state: HashMap<InputCommand, KeyState>
+}
+
+impl Emulator {
+    /// Loads a rom from disk, along with the flash contents from a `.sav` file next to it.
+    pub fn load_rom_file(&mut self, path: &Path) -> Result<(), RomError> {
+        let bytes = std::fs::read(path)?;
+        self.load_rom(&bytes)?;
+
+        let save_path = path.with_extension("sav");
+        if let CartridgeType::Cart2m(cart) = &mut self.cpu_bus.cartridge {
+            match std::fs::read(&save_path) {
+                Ok(save) => {
+                    if cart.load_save_data(&save) {
+                        warn!(" - restored save from {}", save_path.display());
+                    } else {
+                        warn!(" - ignoring malformed save file {}", save_path.display());
+                    }
+                    cart.unsaved = false;
+                }
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => { warn!(" - couldn't read {}: {e}", save_path.display()); }
+            }
+        }
+        self.save_path = Some(save_path);
+
+        for symbols in [path.with_extension("dbg"), path.with_extension("lbl")] {
+            if symbols.exists() {
+                if let Err(e) = self.load_symbols(&symbols) {
+                    warn!(" - couldn't load symbols from {}: {e}", symbols.display());
+                }
+                break
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the cartridge's modified flash sectors out to `save_path`, if anything changed since the last write.
+    /// Happens by itself once the game stops writing for a bit; call it before exiting so nothing's lost.
+    pub fn flush_cartridge_save(&mut self) {
+        let (CartridgeType::Cart2m(cart), Some(path)) = (&mut self.cpu_bus.cartridge, &self.save_path) else {
+            return
+        };
+        if !cart.unsaved {
+            return
+        }
+
+        cart.unsaved = false;
+        match std::fs::write(path, cart.save_data()) {
+            Ok(()) => { debug!("wrote save to {}", path.display()); }
+            Err(e) => { warn!("couldn't write save to {}: {e}", path.display()); }
+        }
+    }
+
+    /// Swaps in a new cartridge and resets the cpus. If the rom is rejected, the current game keeps running.
+    pub fn load_rom(&mut self, bytes: &[u8]) -> Result<(), RomError> {
+        warn!("loading new rom from memory, size: {}", bytes.len());
+        let cartridge = CartridgeType::from_slice(bytes)?;
+        self.flush_cartridge_save();
+        self.save_path = None;
+        self.cpu_bus.cartridge = cartridge;
+        self.rom_hash = self.cpu_bus.cartridge.rom_hash();
+        // they belong to the last game
+        self.save_slots = Default::default();
+        warn!(" - cartridge loaded from memory");
+        self.cpu.reset();
+        warn!(" - cpu reset");
+        self.acp.reset();
+        warn!(" - acp reset");
+        self.blitter.clear_irq_trigger();
+        warn!(" - blitter irq cleared");
+        self.rewind.clear();
+        self.debugger.symbols = Default::default();
+        Ok(())
+    }
+}
+
+impl Debug for Emulator {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Emulator")
+            .field("cpu_bus", &self.cpu_bus)
+            .field("acp_bus", &self.acp_bus)
+            .field("cpu", &self.cpu)
+            .field("acp", &self.acp)
+            .field("blitter", &self.blitter)
+            .field("clock_cycles_to_vblank", &self.clock_cycles_to_vblank)
+            .field("frame_count", &self.frame_count)
+            .field("last_emu_tick", &self.last_emu_tick);
+
+        Ok(())
+    }}
+
+impl Emulator {
+    pub fn wasm_init(&mut self) {
+        if self.play_state == WasmInit {
+            self.play_state = Playing;
+            self.last_emu_tick = get_now_ms();
+            self.last_render_time = get_now_ms();
+        }
+    }
+
+    pub fn init() -> Self {
+        Self::with_bus(CpuBus::default())
+    }
+
+    /// Like [`Emulator::init`], but with the power-on garbage in the framebuffers seeded,
+    /// so that two runs fed the same inputs end up bit-identical.
+    pub fn init_seeded(seed: u64) -> Self {
+        Self::with_bus(CpuBus::with_seed(seed))
+    }
+
+    fn with_bus(mut bus: CpuBus) -> Self {
+        let play_state = WasmInit;
+
+        let mut cpu = W65C02S::new();
+        cpu.step(&mut bus); // take one initial step, to get through the reset vector
+        let acp = W65C02S::new();
+
+        let blitter = Blitter::default();
+
+        let last_cpu_tick_ms = get_now_ms();
+        let cpu_frequency_hz = 3_579_545.0; // Precise frequency
+        let cpu_ns_per_cycle = 1_000_000_000.0 / cpu_frequency_hz; // Nanoseconds per cycle
+
+        let last_render_time = get_now_ms();
+        let rom_hash = bus.cartridge.rom_hash();
+
+        Emulator {
+            play_state,
+            cpu_bus: bus,
+            acp_bus: AcpBus::default(),
+            cpu,
+            acp,
+            blitter,
+            instant_blit: false,
+
+            clock_cycles_to_vblank: CYCLES_PER_FRAME,
+            acp_cycle_accumulator: 0,
+            frame_count: 0,
+            last_emu_tick: last_cpu_tick_ms,
+            cpu_frequency_hz,
+            cpu_ns_per_cycle,
+            last_render_time,
+            audio_out: None,
+            audio_pacing: false,
+            wait_counter: 0,
+
+            save_slots: Default::default(),
+            rewind: RewindBuffer::default(),
+            rewinding: false,
+            rewind_accumulator: 0,
+            save_path: None,
+            rom_hash,
+            frames_since_flash_write: 0,
+            debugger: Debugger::default(),
+            tracer: None,
+            audio_capture: None,
+
+            input_state: Default::default(),
+        }
+    }
+
+    pub fn process_cycles(&mut self, is_web: bool) {
+        self.process_inputs();
+
+        if self.play_state != Playing {
+            return
+        }
+
+        // web redraw
+        let now_ms = get_now_ms();
+        let mut elapsed_ms = now_ms - self.last_emu_tick;
+
+        if elapsed_ms > 33.0 {
+            warn!("emulator took more than 33ms to process cycles");
+            elapsed_ms = 16.667;
+        }
+
+        let elapsed_ns = elapsed_ms * 1000000.0;
+        let remaining_cycles: i32 = (elapsed_ns / self.cpu_ns_per_cycle) as i32;
+
+        if self.rewinding {
+            // go backwards at the same speed we'd go forwards
+            self.rewind_accumulator += remaining_cycles;
+            while self.rewind_accumulator >= CYCLES_PER_FRAME {
+                self.rewind_accumulator -= CYCLES_PER_FRAME;
+                self.rewind_frame();
+            }
+        } else {
+            let cycles = self.paced_cycles(remaining_cycles);
+            self.run_cycles(cycles);
+        }
+
+        self.last_emu_tick = now_ms;
+
+        if !is_web && (now_ms - self.last_render_time) >= 16.67 {
+            debug!("time since last render: {}", now_ms - self.last_render_time);
+            self.last_render_time = now_ms;
+        }
+    }
+
+    /// Applies pending inputs and runs until the next vblank, independent of wall-clock time.
+    /// Returns the number of CPU cycles executed.
+    pub fn run_frame(&mut self) -> i32 {
+        self.process_inputs();
+
+        let frame = self.frame_count;
+        let mut cycles = 0;
+        while self.frame_count == frame && !self.debugger.is_halted() {
+            cycles += self.step_instruction();
+        }
+        cycles
+    }
+
+    /// Runs whole instructions until at least `cycles` CPU cycles have elapsed.
+    /// Returns the number of CPU cycles actually executed, which may overshoot by part of an instruction.
+    pub fn run_cycles(&mut self, cycles: i32) -> i32 {
+        let mut executed = 0;
+        while executed < cycles && !self.debugger.is_halted() {
+            executed += self.step_instruction();
+        }
+        executed
+    }
+
+    /// Executes a single CPU instruction, and everything else on the board for the cycles it took.
+    /// Returns the number of CPU cycles the instruction took.
+    pub fn step_instruction(&mut self) -> i32 {
+        // the acp was stopped by the debugger partway through its share of the last instruction
+        if self.acp_cycle_accumulator > 0 {
+            self.run_acp();
+            if self.debugger.is_halted() {
+                return 0
+            }
+        }
+
+        if self.check_breakpoints(Processor::Cpu) {
+            return 0
+        }
+
+        if self.cpu.get_state() == AwaitingInterrupt {
+            self.wait_counter += 1;
+            // get cpu's current asm code
+        } else if self.wait_counter > 0 {
+            // warn!("waited {} cycles", self.wait_counter);
+            self.wait_counter = 0;
+        }
+
+        self.trace_instruction(Processor::Cpu);
+        let pc = self.cpu.get_pc();
+        let _ = self.cpu.step(&mut Board { bus: &mut self.cpu_bus, blitter: &mut self.blitter, instant_blit: self.instant_blit });
+        self.check_step(Processor::Cpu);
+        self.check_watchpoints(Processor::Cpu, pc);
+        // clear interrupts after a step
+        // self.cpu.set_nmi(false);
+        // self.cpu.set_irq(false);
+
+        let cpu_cycles = self.cpu_bus.clear_cycles() as i32;
+        self.trace_cycles(Processor::Cpu, cpu_cycles);
+
+        // pass aram to acp
+        if self.cpu_bus.system_control.acp_enabled() {
+            self.acp_cycle_accumulator += cpu_cycles * 4;
+            self.run_acp();
+        }
+
+        self.clock_cycles_to_vblank -= cpu_cycles;
+        if self.clock_cycles_to_vblank <= 0 {
+            self.vblank();
+        }
+
+        cpu_cycles
+    }
+
+    fn run_acp(&mut self) {
+        self.acp_bus.aram = self.cpu_bus.aram.take();
+
+        if self.cpu_bus.system_control.clear_acp_reset() {
+            self.acp.reset();
+        }
+
+        if self.cpu_bus.system_control.clear_acp_nmi() {
+            self.acp.set_nmi(true);
+        }
+
+        while self.acp_cycle_accumulator > 0 && !self.debugger.is_halted() {
+            if self.check_breakpoints(Processor::Acp) {
+                break
+            }
+
+            self.trace_instruction(Processor::Acp);
+            let pc = self.acp.get_pc();
+            let _ = self.acp.step(&mut self.acp_bus);
+            let acp_cycles = self.acp_bus.clear_cycles() as i32;
+            self.acp_cycle_accumulator -= acp_cycles;
+            self.trace_cycles(Processor::Acp, acp_cycles);
+            self.check_step(Processor::Acp);
+            self.check_watchpoints(Processor::Acp, pc);
+
+            // clear stuff ig
+            self.acp.set_irq(false);
+            self.acp.set_nmi(false);
+
+            if self.acp_bus.irq_counter <= 0 {
+                self.acp_bus.irq_counter = self.cpu_bus.system_control.sample_rate() as i32 * 4;
+                self.acp.set_irq(true);
+
+                let sample_rate = self.cpu_frequency_hz / self.cpu_bus.system_control.sample_rate() as f64;
+                self.capture_sample(self.acp_bus.sample, sample_rate);
+                if let Some(audio) = &mut self.audio_out {
+                    audio.push_sample(self.acp_bus.sample, sample_rate);
+                }
+            }
+        }
+        self.cpu_bus.aram = self.acp_bus.aram.take();
+    }
+
+    fn vblank(&mut self) {
+        self.clock_cycles_to_vblank += CYCLES_PER_FRAME;
+        self.frame_count += 1;
+
+        if self.cpu_bus.vblank_nmi_enabled() {
+            self.cpu.set_nmi(true);
+            debug!("vblanked");
+        }
+
+        if self.rewind.enabled() {
+            let state = self.save_state();
+            self.rewind.push(state);
+        }
+
+        if let CartridgeType::Cart2m(cart) = &mut self.cpu_bus.cartridge {
+            if std::mem::take(&mut cart.written) {
+                self.frames_since_flash_write = 0;
+            } else {
+                self.frames_since_flash_write = self.frames_since_flash_write.saturating_add(1);
+            }
+        }
+        if self.frames_since_flash_write >= SAVE_FLUSH_DELAY_FRAMES {
+            self.flush_cartridge_save();
+        }
+    }
+
+    /// Steps back to the previous frame in the rewind history. Returns false once the history runs out.
+    pub fn rewind_frame(&mut self) -> bool {
+        let mut rewind = std::mem::take(&mut self.rewind);
+        let rewound = match rewind.pop() {
+            Some(state) => {
+                if let Err(e) = self.load_state(state) {
+                    warn!("couldn't rewind: {e}");
+                }
+                true
+            }
+            None => false,
+        };
+        self.rewind = rewind;
+        rewound
+    }
+
+    pub fn set_input_state(&mut self, command: InputCommand, pressed: bool) {
+        if let Some(ks) = self.input_state.get(&command) {
+            self.input_state.insert(command, ks.update_state(pressed));
+        } else {
+            self.input_state.insert(command, KeyState::new(pressed));
+        }
+    }
+
+    pub fn process_inputs(&mut self) {
+        let keys: Vec<_> = self.input_state.keys().cloned().collect();  // Clone keys to avoid borrowing conflicts
+
+        if !keys.is_empty() && self.play_state == WasmInit {
+            self.play_state = Playing;
+        }
+
+        for key in &keys {
+            match key {
+                Controller1(button) => { self.set_gamepad_input(0, key, button); }
+                Controller2(button) => { self.set_gamepad_input(1, key, button); }
+                PlayPause => {
+                    if self.input_state[key] == JustReleased {
+                        match self.play_state {
+                            Paused => { self.resume(); }
+                            Playing => { self.play_state = Paused; }
+                            WasmInit => { self.play_state = Playing; }
+                        }
+                    }
+                }
+                Rewind => {
+                    self.rewinding = self.input_state[key].is_pressed();
+                }
+                SoftReset => {
+                    if self.input_state[key] == JustReleased {
+                        self.soft_reset();
+                    }
+                }
+                HardReset => {
+                    if self.input_state[key] == JustReleased {
+                        self.hard_reset();
+                    }
+                }
+                QuickSave(slot) => {
+                    if self.input_state[key] == JustReleased {
+                        self.quick_save(*slot as usize);
+                    }
+                }
+                QuickLoad(slot) => {
+                    if self.input_state[key] == JustReleased {
+                        if let Err(e) = self.quick_load(*slot as usize) {
+                            warn!("couldn't quick-load slot {slot}: {e}");
+                        }
+                    }
+                }
+            }
+            self.input_state.insert(*key, self.input_state[key].update());
+        }
+    }
+    /// Pulses reset on the CPU and ACP, leaving everything else as is.
+    pub fn soft_reset(&mut self) {
+        warn!("soft reset");
+        self.cpu.reset();
+        self.acp.reset();
+    }
+
+    /// Back to power-on state, with the same cartridge still plugged in.
+    pub fn hard_reset(&mut self) {
+        warn!("hard reset");
+        let mut bus = CpuBus::default();
+        std::mem::swap(&mut bus.cartridge, &mut self.cpu_bus.cartridge);
+        bus.cartridge.reset();
+        // watchpoints belong to whoever's debugging, not the machine
+        std::mem::swap(&mut bus.watchpoints.list, &mut self.cpu_bus.watchpoints.list);
+        let mut acp_bus = AcpBus::default();
+        std::mem::swap(&mut acp_bus.watchpoints.list, &mut self.acp_bus.watchpoints.list);
+
+        self.cpu_bus = bus;
+        self.acp_bus = acp_bus;
+        self.blitter = Blitter::default();
+        self.cpu = W65C02S::new();
+        self.acp = W65C02S::new();
+        self.clock_cycles_to_vblank = CYCLES_PER_FRAME;
+        self.acp_cycle_accumulator = 0;
+        self.frame_count = 0;
+    }
+
+    /// Where a quick save slot is kept on disk, for roms that came from a file.
+    pub fn save_slot_path(&self, slot: usize) -> Option<PathBuf> {
+        self.save_path.as_ref().map(|path| path.with_extension(format!("ss{slot}")))
+    }
+
+    pub fn quick_save(&mut self, slot: usize) {
+        if slot >= SAVE_SLOTS {
+            return
+        }
+        let state = self.save_state();
+        if let Some(path) = self.save_slot_path(slot) {
+            match std::fs::write(&path, &state) {
+                Ok(()) => { warn!("saved state to {}", path.display()); }
+                Err(e) => { warn!("couldn't write {}, slot {slot} only lasts until exit: {e}", path.display()); }
+            }
+        } else {
+            warn!("saved state to slot {slot}");
+        }
+        self.save_slots[slot] = Some(state);
+    }
+
+    pub fn quick_load(&mut self, slot: usize) -> Result<(), SaveStateError> {
+        let state = match (self.save_slots.get(slot).cloned().flatten(), self.save_slot_path(slot)) {
+            (Some(state), _) => state,
+            (None, Some(path)) if path.exists() => std::fs::read(path)?,
+            _ => return Err(SaveStateError::EmptySlot(slot)),
+        };
+        self.load_state(&state)?;
+        self.save_slots[slot] = Some(state);
+        warn!("loaded state from slot {slot}");
+        Ok(())
+    }
+
+    fn set_gamepad_input(&mut self, gamepad: usize, key: &InputCommand, button: &ControllerButton) {
+        let gamepad = &mut self.cpu_bus.system_control.gamepads[gamepad];
+        match button {
+            Up =>     { gamepad.up    = self.input_state[key].is_pressed(); }
+            Down =>   { gamepad.down  = self.input_state[key].is_pressed(); }
+            Left =>   { gamepad.left  = self.input_state[key].is_pressed(); }
+            Right =>  { gamepad.right = self.input_state[key].is_pressed(); }
+            B =>      { gamepad.b     = self.input_state[key].is_pressed(); }
+            A =>      { gamepad.a     = self.input_state[key].is_pressed(); }
+            Start =>  { gamepad.start = self.input_state[key].is_pressed(); }
+            C =>      { gamepad.c     = self.input_state[key].is_pressed(); }
+        }
+    }
+}
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,145 @@
+//! Emulator-level behavior that has to hold up across resets and the like.
+
+use w65c02s::{System, W65C02S};
+use crate::cartridges::CartridgeType;
+use crate::emulator::{Board, Emulator, SAVE_FLUSH_DELAY_FRAMES};
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoint};
+
+#[test]
+fn hard_reset_keeps_instant_blit() {
+    let mut emulator = Emulator::init_seeded(0);
+    emulator.instant_blit = true;
+    emulator.hard_reset();
+    assert!(emulator.instant_blit, "hard reset turned instant blits off");
+}
+
+#[test]
+fn hard_reset_keeps_watchpoints() {
+    let mut emulator = Emulator::init_seeded(0);
+    let cpu_watch = Watchpoint { target: WatchTarget::Ram(0, 0x0203), kind: WatchKind::Write, value: None };
+    let aram_watch = Watchpoint { target: WatchTarget::Aram(0x040), kind: WatchKind::Read, value: Some(0x12) };
+    emulator.add_watchpoint(cpu_watch);
+    emulator.add_watchpoint(aram_watch);
+
+    emulator.hard_reset();
+    assert_eq!(emulator.watchpoints(), [cpu_watch, aram_watch]);
+    assert_eq!(emulator.acp_bus.watchpoints.list, [aram_watch]);
+}
+
+/// Makes cpu bus accesses the way an instruction would, clocking the blitter after each one.
+fn access(emulator: &mut Emulator, writes: &[(u16, u8)]) {
+    let mut cpu = W65C02S::new();
+    let mut board = Board { bus: &mut emulator.cpu_bus, blitter: &mut emulator.blitter, instant_blit: false };
+    for &(address, data) in writes {
+        board.write(&mut cpu, address, data);
+    }
+}
+
+/// Idles the cpu on a ram read for `cycles` cycles.
+fn idle(emulator: &mut Emulator, cycles: usize) {
+    let mut cpu = W65C02S::new();
+    let mut board = Board { bus: &mut emulator.cpu_bus, blitter: &mut emulator.blitter, instant_blit: false };
+    for _ in 0..cycles {
+        board.read(&mut cpu, 0x0000);
+    }
+}
+
+const DMA_ON_COLORFILL: u8 = 0b0000_1001;
+const DMA_OFF_CPU_TO_FRAMEBUFFER: u8 = 0b0010_1000;
+const FILL: u8 = 0x0C;
+
+/// An 8x8 fill of [`FILL`] at (0, 0), into a framebuffer that starts out all $FF.
+fn start_fill(emulator: &mut Emulator) {
+    emulator.cpu_bus.system_control.banking_register.0 = 0;
+    emulator.cpu_bus.framebuffers[0].borrow_mut().fill(0xFF);
+    access(emulator, &[
+        (0x2007, DMA_ON_COLORFILL),
+        (0x4000, 0), (0x4001, 0), (0x4004, 8), (0x4005, 8), (0x4007, !FILL),
+        (0x4006, 1),
+    ]);
+    assert!(emulator.blitter.is_blitting());
+}
+
+fn filled(emulator: &Emulator) -> usize {
+    let fb = emulator.cpu_bus.framebuffers[0].borrow();
+    (0..8).flat_map(|y| (0..8).map(move |x| x + y * 128)).filter(|&i| fb[i] == FILL).count()
+}
+
+#[test]
+fn cpu_only_reaches_blitter_registers_while_dma_is_on() {
+    let mut emulator = Emulator::init_seeded(0);
+    start_fill(&mut emulator);
+
+    // would be framebuffer pixel $1000 with dma off
+    access(&mut emulator, &[(0x5000, 0x42)]);
+    idle(&mut emulator, 80);
+
+    assert!(!emulator.blitter.is_blitting());
+    assert_eq!(emulator.cpu_bus.framebuffers[0].borrow()[0x1000], 0xFF, "cpu wrote to the framebuffer while the blitter owned it");
+    assert_eq!(filled(&emulator), 64);
+}
+
+#[test]
+fn blit_keeps_counting_without_writing_while_dma_is_off() {
+    let mut emulator = Emulator::init_seeded(0);
+    start_fill(&mut emulator);
+
+    // hand graphics memory to the cpu partway through the first row, and write a pixel of our own
+    idle(&mut emulator, 3);
+    access(&mut emulator, &[(0x2007, DMA_OFF_CPU_TO_FRAMEBUFFER), (0x4000 + 0x1000, 0x42)]);
+    let before = filled(&emulator);
+    idle(&mut emulator, 80);
+
+    assert!(!emulator.blitter.is_blitting(), "blit stalled instead of counting through");
+    assert_eq!(emulator.cpu_bus.framebuffers[0].borrow()[0x1000], 0x42, "cpu couldn't write the framebuffer with dma off");
+    assert!(before > 0 && before < 8, "expected part of the first row to be filled, got {before} pixels");
+    assert_eq!(filled(&emulator), before, "blitter wrote to the framebuffer with dma off");
+}
+
+/// Programs one byte of the 2M cart's flash, the way a game saving would.
+fn program_flash(emulator: &mut Emulator, address: u16, data: u8) {
+    let CartridgeType::Cart2m(cart) = &mut emulator.cpu_bus.cartridge else {
+        panic!("expected a 2M cartridge")
+    };
+    for (address, data) in [(0x4555, 0xAA), (0x42AA, 0x55), (0x4555, 0xA0), (address, data)] {
+        cart.write_byte(address, data);
+    }
+}
+
+#[test]
+fn flash_saves_wait_for_writing_to_stop() {
+    let dir = std::env::temp_dir().join(format!("gametank-save-flush-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.gtr");
+    let sav = dir.join("game.sav");
+    std::fs::write(&rom, vec![0x91; 128 * 0x4000]).unwrap();
+
+    let mut emulator = Emulator::init_seeded(0);
+    emulator.load_rom_file(&rom).unwrap();
+
+    // a game writing a little every frame
+    for frame in 0..SAVE_FLUSH_DELAY_FRAMES * 2 {
+        program_flash(&mut emulator, 0x4000 + frame as u16, 0);
+        emulator.vblank();
+        assert!(!sav.exists(), "saved while the game was still writing, on frame {frame}");
+    }
+
+    for _ in 0..SAVE_FLUSH_DELAY_FRAMES - 1 {
+        emulator.vblank();
+    }
+    assert!(!sav.exists(), "saved before writing had settled");
+    emulator.vblank();
+    let saved = std::fs::read(&sav).expect("nothing saved once writing stopped");
+
+    // nothing new to write, and swapping roms flushes straight away
+    std::fs::remove_file(&sav).unwrap();
+    for _ in 0..SAVE_FLUSH_DELAY_FRAMES * 2 {
+        emulator.vblank();
+    }
+    assert!(!sav.exists(), "saved again without anything changing");
+    program_flash(&mut emulator, 0x4000, 0);
+    emulator.load_rom(&[0xEA; 0x8000]).unwrap();
+    assert!(std::fs::read(&sav).unwrap() == saved, "swapping roms lost the last write");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
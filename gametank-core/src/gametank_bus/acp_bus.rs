@@ -1,6 +1,7 @@
 use tracing::{error};
 use w65c02s::{System, W65C02S};
-use crate::emulator::gametank_bus::Bus;
+use crate::gametank_bus::Bus;
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoints};
 
 pub(crate) type ARAM = Box<[u8; 0x1000]>;
 
@@ -10,7 +11,10 @@ pub struct AcpBus {
     pub irq_counter: i32,
 
     pub sample: u8,
-    pub aram: Option<ARAM>
+    pub aram: Option<ARAM>,
+
+    /// only ever holds aram watchpoints
+    pub watchpoints: Watchpoints,
 }
 
 impl AcpBus {
@@ -18,6 +22,7 @@ impl AcpBus {
         if let Some(aram) = &mut self.aram {
             match address {
                 0x0000..0x1000 => {
+                    self.watchpoints.check(address, Some(WatchTarget::Aram(address)), WatchKind::Write, data);
                     aram[address as usize] = data;
                 }
                 0x8000..=0xFFFF => {
@@ -30,9 +35,11 @@ impl AcpBus {
         }
     }
 
-    pub(crate) fn read_byte(&self, address: u16) -> u8 {
+    pub(crate) fn read_byte(&mut self, address: u16) -> u8 {
         if let Some(aram) = &self.aram {
-            aram[(address as usize) % 0x1000]
+            let data = aram[(address as usize) % 0x1000];
+            self.watchpoints.check(address, Some(WatchTarget::Aram(address % 0x1000)), WatchKind::Read, data);
+            data
         } else {
             error!("acp contention on audio ram");
             0
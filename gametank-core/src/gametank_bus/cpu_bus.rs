@@ -1,18 +1,18 @@
 use w65c02s::{System, W65C02S};
-use rand::{thread_rng, Rng};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
 use std::cell::Ref;
 use tracing::{debug, warn};
-use web_sys::js_sys::Atomics::add;
-use crate::emulator::cartridges::cart2m::Cartridge2M;
-use crate::emulator::cartridges::CartridgeType;
-use crate::emulator::gametank_bus::Bus;
-use crate::emulator::gametank_bus::reg_system_control::*;
-use crate::emulator::gamepad::GamePad;
-use crate::emulator::gametank_bus::ARAM;
-use crate::emulator::gametank_bus::cpu_bus::ByteDecorator::{AudioRam, CpuStack, SystemRam, Unreadable, Vram, ZeroPage};
-use crate::emulator::gametank_bus::reg_blitter::{BlitStart, BlitterRegisters};
-use crate::emulator::gametank_bus::reg_etc::{new_framebuffer, BankingRegister, BlitterFlags, FrameBuffer, GraphicsMemoryMap, SharedFrameBuffer};
-use crate::emulator::gametank_bus::reg_system_control::*;
+use crate::cartridges::CartridgeType;
+use crate::gametank_bus::Bus;
+use crate::gametank_bus::reg_system_control::*;
+use crate::gametank_bus::via::Via;
+use crate::gamepad::GamePad;
+use crate::gametank_bus::ARAM;
+use crate::gametank_bus::cpu_bus::ByteDecorator::{AudioRam, CpuStack, SystemRam, Unreadable, VersatileInterfaceAdapter, Vram, ZeroPage};
+use crate::gametank_bus::reg_blitter::{BlitStart, BlitterRegisters};
+use crate::gametank_bus::reg_etc::{new_framebuffer, BankingRegister, BlitterFlags, FrameBuffer, GraphicsMemoryMap, SharedFrameBuffer};
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoints};
 
 const _HELLO_WORLD_GTR: &[u8] = include_bytes!("../roms/hello.gtr");
 const _MICROVOID_GTR: &[u8] = include_bytes!("../roms/microvoid.gtr");
@@ -31,7 +31,7 @@ pub enum ByteDecorator {
     CpuStack(u8),
     SystemRam(u8),
     // SCR(u8),
-    // VersatileInterfaceAdapter(u8),
+    VersatileInterfaceAdapter(u8),
     AudioRam(u8),
     Vram(u8),
     Framebuffer(u8),
@@ -40,6 +40,15 @@ pub enum ByteDecorator {
     Unreadable(u8),
 }
 
+impl ByteDecorator {
+    pub fn byte(self) -> u8 {
+        match self {
+            ZeroPage(b) | CpuStack(b) | SystemRam(b) | VersatileInterfaceAdapter(b) | AudioRam(b) | Vram(b) |
+            ByteDecorator::Framebuffer(b) | ByteDecorator::Aram(b) | Unreadable(b) => b,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CpuBus {
     cycles: u8,
@@ -55,11 +64,20 @@ pub struct CpuBus {
 
     pub aram: Option<ARAM>,
     pub cartridge: CartridgeType,
+
+    pub watchpoints: Watchpoints,
 }
 
 impl Default for CpuBus {
     fn default() -> Self {
-        let mut rng = thread_rng();
+        Self::with_seed(thread_rng().gen())
+    }
+}
+
+impl CpuBus {
+    /// Power-on state, with the framebuffers' garbage derived from `seed` so runs can be reproduced.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
 
         let bus = Self {
             cycles: 0,
@@ -67,7 +85,7 @@ impl Default for CpuBus {
                 reset_acp: 0,
                 nmi_acp: 0,
                 banking_register: BankingRegister(0),
-                via_regs: [0; 16],
+                via: Via::default(),
                 audio_enable_sample_rate: 0,
                 dma_flags: BlitterFlags(0b0111_1111),
                 gamepads: [GamePad::default(), GamePad::default()]
@@ -88,9 +106,10 @@ impl Default for CpuBus {
             ram_banks: Box::new([[0; 0x2000]; 4]),
             framebuffers: [new_framebuffer(0x00), new_framebuffer(0xFF)],
             vram_banks: Box::new([[0; 256*256]; 8]),
-            cartridge: CartridgeType::from_slice(CURRENT_GAME),
+            cartridge: CartridgeType::from_slice(CURRENT_GAME).expect("built-in rom should be valid"),
             aram: Some(Box::new([0; 0x1000])),
             vram_quad_written: [false; 32],
+            watchpoints: Watchpoints::default(),
         };
 
         for p in bus.framebuffers[0].borrow_mut().iter_mut() {
@@ -111,11 +130,10 @@ impl CpuBus {
         self.framebuffers[fb].borrow()
     }
 
-    fn update_flash_shift_register(&mut self, next_val: u8) {
+    fn update_flash_shift_register(&mut self, old_val: u8, next_val: u8) {
         match &mut self.cartridge {
             CartridgeType::Cart2m(cartridge) => {
                 // For now, assuming that if we're using Flash2M hardware, we're behaving ourselves
-                let old_val = self.system_control.via_regs[VIA_IORA]; // Get the previous value from the VIA
                 let rising_bits = next_val & !old_val;
 
                 if rising_bits & VIA_SPI_BIT_CLK != 0 {
@@ -124,11 +142,6 @@ impl CpuBus {
                     cartridge.bank_shifter |= ((old_val & VIA_SPI_BIT_MOSI) != 0) as u8; // Set the last bit based on MOSI
                 } else if rising_bits & VIA_SPI_BIT_CS != 0 {
                     // Flash cart CS is connected to latch clock
-                    if (cartridge.bank_mask ^ cartridge.bank_shifter as u16) & 0x80 != 0 {
-                        // TODO: support saving
-                        // self.save_nvram(); // Assuming this is defined elsewhere or is a method within CpuBus
-                        warn!("Saving is not yet supported");
-                    }
                     cartridge.bank_mask = cartridge.bank_shifter as u16; // Update the bank mask
                     debug!("Flash bank mask set to 0x{:x}", cartridge.bank_mask);
                 }
@@ -137,7 +150,33 @@ impl CpuBus {
         }
     }
 
+    /// The watchable memory currently mapped at `address`.
+    fn watch_location(&self, address: u16) -> Option<WatchTarget> {
+        match address {
+            0x0000..=0x1FFF => Some(WatchTarget::Ram(self.system_control.get_ram_bank() as u8, address)),
+            0x3000..=0x3FFF => Some(WatchTarget::Aram(address - 0x3000)),
+            0x4000..=0x7FFF => match self.system_control.get_graphics_memory_map() {
+                GraphicsMemoryMap::FrameBuffer => {
+                    let fb = self.system_control.banking_register.framebuffer() as u8;
+                    Some(WatchTarget::Framebuffer(fb, address - 0x4000))
+                }
+                GraphicsMemoryMap::VRAM => {
+                    let vram_page = self.system_control.banking_register.vram_page();
+                    let quadrant = self.blitter.vram_quadrant() as u16;
+                    Some(WatchTarget::Vram(vram_page, address - 0x4000 + quadrant*(128*128)))
+                }
+                GraphicsMemoryMap::BlitterRegisters => None,
+            },
+            _ => None,
+        }
+    }
+
     pub fn write_byte(&mut self, address: u16, data: u8) {
+        if !self.watchpoints.list.is_empty() {
+            let location = self.watch_location(address);
+            self.watchpoints.check(address, location, WatchKind::Write, data);
+        }
+
         match address {
             // system RAM
             0x0000..=0x1FFF => {
@@ -153,14 +192,13 @@ impl CpuBus {
 
             // versatile interface adapter (GPIO, timers)
             0x2800..=0x280F => {
-                let register = (address & 0xF) as usize;
-                match (address & 0xF) as usize {
-                    VIA_IORA => {
-                        self.update_flash_shift_register(data);
-                    }
-                    _ => {}
+                // the flash cart's shift register hangs off the port A pins, so writes to either ORA or DDRA can clock it
+                let old_pins = self.system_control.via.port_a_pins();
+                self.system_control.via.write_byte((address & 0xF) as usize, data);
+                let new_pins = self.system_control.via.port_a_pins();
+                if old_pins != new_pins {
+                    self.update_flash_shift_register(old_pins, new_pins);
                 }
-                self.system_control.via_regs[register] = data;
             }
 
             // audio RAM
@@ -189,6 +227,11 @@ impl CpuBus {
                     }
                 }
             }
+
+            // Cartridge
+            0x8000..=0xFFFF => {
+                self.cartridge.write_byte(address - 0x8000, data);
+            }
             _ => {
                 warn!("Attempted to write read-only memory at: ${:02X}", address);
             }
@@ -196,6 +239,12 @@ impl CpuBus {
     }
 
     pub fn read_byte(&mut self, address: u16) -> u8 {
+        if !self.watchpoints.list.is_empty() {
+            let location = self.watch_location(address);
+            let data = self.peek_byte(address);
+            self.watchpoints.check(address, location, WatchKind::Read, data);
+        }
+
         match address {
             // system RAM
             0x0000..=0x1FFF => {
@@ -209,8 +258,7 @@ impl CpuBus {
 
             // versatile interface adapter (GPIO, timers)
             0x2800..=0x280F => {
-                let register = (address & 0xF) as usize;
-                return self.system_control.via_regs[register]
+                return self.system_control.via.read_byte((address & 0xF) as usize)
             }
 
             // audio RAM
@@ -256,7 +304,7 @@ impl CpuBus {
             0x0100..=0x01FF => { CpuStack(self.ram_banks[self.system_control.get_ram_bank()][address as usize]) },
             0x0200..=0x1FFF => { SystemRam(self.ram_banks[self.system_control.get_ram_bank()][address as usize]) },
             0x2000..=0x2009 => { Unreadable(self.system_control.peek_byte(address)) },
-            // 0x2800..=0x280F => { Via(self.system_control.via_regs[(address & 0xF) as usize]) },
+            0x2800..=0x280F => { VersatileInterfaceAdapter(self.system_control.via.peek_byte((address & 0xF) as usize)) },
             0x3000..=0x3FFF => { AudioRam(if let Some(aram) = &self.aram { aram[(address - 0x3000) as usize] } else { 0 }) },
             0x4000..=0x7FFF => {
                 match self.system_control.get_graphics_memory_map() {
@@ -278,6 +326,44 @@ impl CpuBus {
         }
     }
 
+    /// Reads a byte the way the cpu would, but without any side effects.
+    pub fn peek_byte(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => self.cartridge.read_byte(address - 0x8000),
+            _ => self.peek_byte_decorated(address).byte(),
+        }
+    }
+
+    /// Writes memory without any side effects, for debuggers. Returns false for registers, which can't be
+    /// written without setting something off.
+    pub fn poke_byte(&mut self, address: u16, data: u8) -> bool {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_banks[self.system_control.get_ram_bank()][address as usize] = data;
+            }
+            0x3000..=0x3FFF => match &mut self.aram {
+                Some(aram) => { aram[(address - 0x3000) as usize] = data; }
+                None => return false,
+            },
+            0x4000..=0x7FFF => match self.system_control.get_graphics_memory_map() {
+                GraphicsMemoryMap::FrameBuffer => {
+                    let fb = self.system_control.banking_register.framebuffer() as usize;
+                    self.framebuffers[fb].borrow_mut()[address as usize - 0x4000] = data;
+                }
+                GraphicsMemoryMap::VRAM => {
+                    let vram_page = self.system_control.banking_register.vram_page() as usize;
+                    let quadrant = self.blitter.vram_quadrant();
+                    self.vram_banks[vram_page][address as usize - 0x4000 + quadrant*(128*128)] = data;
+                    self.vram_quad_written[quadrant + vram_page * 4] = true;
+                }
+                GraphicsMemoryMap::BlitterRegisters => return false,
+            },
+            0x8000..=0xFFFF => { self.cartridge.poke_byte(address - 0x8000, data); }
+            _ => return false,
+        }
+        true
+    }
+
     pub fn vblank_nmi_enabled(&self) -> bool {
         self.system_control.dma_flags.dma_nmi()
     }
@@ -286,12 +372,15 @@ impl CpuBus {
 impl System for CpuBus {
     fn read(&mut self, _: &mut W65C02S, addr: u16) -> u8 {
         self.cycles += 1;
-        self.read_byte(addr)
+        let data = self.read_byte(addr);
+        self.system_control.via.tick();
+        data
     }
 
     fn write(&mut self, _: &mut W65C02S, addr: u16, data: u8) {
         self.cycles += 1;
         self.write_byte(addr, data);
+        self.system_control.via.tick();
     }
 }
 
@@ -4,6 +4,7 @@ mod reg_etc;
 mod cpu_bus;
 mod reg_system_control;
 mod reg_blitter;
+pub mod via;
 
 pub use bus::*;
 pub use acp_bus::*;
@@ -1,6 +1,7 @@
 use tracing::{debug, warn};
-use crate::emulator::gamepad::GamePad;
-use crate::emulator::gametank_bus::reg_etc::{BankingRegister, BlitterFlags, GraphicsMemoryMap};
+use crate::gamepad::GamePad;
+use crate::gametank_bus::reg_etc::{BankingRegister, BlitterFlags, GraphicsMemoryMap};
+use crate::gametank_bus::via::Via;
 
 pub const VIA_IORB: usize    = 0x0;
 pub const VIA_IORA: usize    = 0x1;
@@ -32,7 +33,7 @@ pub struct SystemControl {
     // has effects on the rest of the system
     pub banking_register: BankingRegister,
 
-    pub via_regs: [u8; 16],
+    pub via: Via,
 
     pub audio_enable_sample_rate: u8,
     pub dma_flags: BlitterFlags,
@@ -54,7 +55,7 @@ impl SystemControl {
             return GraphicsMemoryMap::FrameBuffer
         }
 
-        return GraphicsMemoryMap::VRAM
+        GraphicsMemoryMap::VRAM
     }
 
     pub fn acp_enabled(&self) -> bool {
@@ -0,0 +1,483 @@
+use crate::gametank_bus::reg_system_control::*;
+use crate::savestate::{SaveStateError, Snapshot, StateReader, StateWriter};
+
+// interrupt flag / enable bits
+pub const VIA_INT_CA2: u8 = 0b0000_0001;
+pub const VIA_INT_CA1: u8 = 0b0000_0010;
+pub const VIA_INT_SR: u8  = 0b0000_0100;
+pub const VIA_INT_CB2: u8 = 0b0000_1000;
+pub const VIA_INT_CB1: u8 = 0b0001_0000;
+pub const VIA_INT_T2: u8  = 0b0010_0000;
+pub const VIA_INT_T1: u8  = 0b0100_0000;
+pub const VIA_INT_ANY: u8 = 0b1000_0000;
+
+/// W65C22 versatile interface adapter, clocked once per CPU cycle.
+///
+/// Nothing on the GameTank drives the CA/CB control lines, but they're modelled anyway so
+/// handshakes and edge interrupts behave if something (like a cartridge) ever does.
+#[derive(Debug, Clone)]
+pub struct Via {
+    pub orb: u8,
+    pub ora: u8,
+    pub ddrb: u8,
+    pub ddra: u8,
+
+    pub t1_counter: u16,
+    pub t1_latch: u16,
+    t1_armed: bool,
+    t1_reload: bool,
+    pb7: bool,
+
+    pub t2_counter: u16,
+    pub t2_latch_low: u8,
+    t2_armed: bool,
+    t2_reload: bool,
+
+    pub sr: u8,
+    sr_bits_left: u8,
+    sr_timer: u16,
+
+    pub acr: u8,
+    pub pcr: u8,
+    pub ifr: u8,
+    pub ier: u8,
+
+    /// levels driven onto the port pins from outside, for bits configured as inputs
+    pub port_a_in: u8,
+    pub port_b_in: u8,
+    ira_latch: u8,
+    irb_latch: u8,
+
+    ca1: bool,
+    ca2: bool,
+    cb1: bool,
+    cb2: bool,
+    ca2_out: bool,
+    cb2_out: bool,
+}
+
+impl Default for Via {
+    fn default() -> Self {
+        Self {
+            orb: 0,
+            ora: 0,
+            ddrb: 0,
+            ddra: 0,
+            t1_counter: 0xFFFF,
+            t1_latch: 0xFFFF,
+            t1_armed: false,
+            t1_reload: false,
+            pb7: true,
+            t2_counter: 0xFFFF,
+            t2_latch_low: 0xFF,
+            t2_armed: false,
+            t2_reload: false,
+            sr: 0,
+            sr_bits_left: 0,
+            sr_timer: 0,
+            acr: 0,
+            pcr: 0,
+            ifr: 0,
+            ier: 0,
+            port_a_in: 0xFF,
+            port_b_in: 0xFF,
+            ira_latch: 0xFF,
+            irb_latch: 0xFF,
+            ca1: true,
+            ca2: true,
+            cb1: true,
+            cb2: true,
+            ca2_out: true,
+            cb2_out: true,
+        }
+    }
+}
+
+impl Via {
+    /// Is the VIA pulling IRQ low?
+    pub fn irq(&self) -> bool {
+        self.ifr & self.ier & 0x7F != 0
+    }
+
+    /// Levels on the port A pins. Outputs drive ORA, inputs float to whatever is driving them.
+    pub fn port_a_pins(&self) -> u8 {
+        (self.ora & self.ddra) | (self.port_a_in & !self.ddra)
+    }
+
+    pub fn port_b_pins(&self) -> u8 {
+        let mut pins = (self.orb & self.ddrb) | (self.port_b_in & !self.ddrb);
+        if self.t1_pb7_enabled() {
+            pins = (pins & 0x7F) | ((self.pb7 as u8) << 7);
+        }
+        pins
+    }
+
+    pub fn ca2_output(&self) -> bool {
+        self.ca2_out
+    }
+
+    pub fn cb2_output(&self) -> bool {
+        self.cb2_out
+    }
+
+    fn t1_free_run(&self) -> bool { self.acr & 0b0100_0000 != 0 }
+    fn t1_pb7_enabled(&self) -> bool { self.acr & 0b1000_0000 != 0 }
+    fn t2_pulse_counting(&self) -> bool { self.acr & 0b0010_0000 != 0 }
+    fn sr_mode(&self) -> u8 { (self.acr >> 2) & 0b111 }
+    fn pa_latching(&self) -> bool { self.acr & 0b0000_0001 != 0 }
+    fn pb_latching(&self) -> bool { self.acr & 0b0000_0010 != 0 }
+
+    fn ca2_mode(&self) -> u8 { (self.pcr >> 1) & 0b111 }
+    fn cb2_mode(&self) -> u8 { (self.pcr >> 5) & 0b111 }
+
+    fn set_flag(&mut self, flag: u8) {
+        self.ifr |= flag;
+    }
+
+    fn clear_flag(&mut self, flag: u8) {
+        self.ifr &= !flag;
+    }
+
+    fn ifr_with_any(&self) -> u8 {
+        let ifr = self.ifr & 0x7F;
+        if self.irq() { ifr | VIA_INT_ANY } else { ifr }
+    }
+
+    /// Advance one phi2 cycle.
+    pub fn tick(&mut self) {
+        // a pulse output only lasts one cycle
+        if self.ca2_mode() == 0b101 {
+            self.ca2_out = true;
+        }
+        if self.cb2_mode() == 0b101 {
+            self.cb2_out = true;
+        }
+
+        self.tick_t1();
+        self.tick_t2();
+        self.tick_sr();
+    }
+
+    fn tick_t1(&mut self) {
+        if self.t1_reload {
+            self.t1_reload = false;
+            self.t1_counter = self.t1_latch;
+            return
+        }
+
+        let (counter, underflow) = self.t1_counter.overflowing_sub(1);
+        self.t1_counter = counter;
+        if !underflow || !self.t1_armed {
+            return
+        }
+
+        self.set_flag(VIA_INT_T1);
+        if self.t1_free_run() {
+            self.pb7 = !self.pb7;
+            self.t1_reload = true;
+        } else {
+            self.pb7 = true;
+            self.t1_armed = false;
+        }
+    }
+
+    fn tick_t2(&mut self) {
+        if self.t2_reload {
+            self.t2_reload = false;
+            return
+        }
+
+        // pulse counting needs PB6 pulses, which nothing on the board generates
+        if self.t2_pulse_counting() {
+            return
+        }
+
+        let (counter, underflow) = self.t2_counter.overflowing_sub(1);
+        self.t2_counter = counter;
+        if underflow && self.t2_armed {
+            self.set_flag(VIA_INT_T2);
+            self.t2_armed = false;
+        }
+    }
+
+    fn sr_period(&self) -> Option<u16> {
+        match self.sr_mode() {
+            0b001 | 0b100 | 0b101 => Some(2 * (self.t2_latch_low as u16 + 2)),
+            0b010 | 0b110 => Some(2),
+            // disabled, or clocked by CB1
+            _ => None,
+        }
+    }
+
+    fn tick_sr(&mut self) {
+        let Some(period) = self.sr_period() else {
+            return
+        };
+        if self.sr_bits_left == 0 && self.sr_mode() != 0b100 {
+            return
+        }
+
+        self.sr_timer = self.sr_timer.saturating_sub(1);
+        if self.sr_timer == 0 {
+            self.sr_timer = period;
+            self.shift();
+        }
+    }
+
+    fn shift(&mut self) {
+        let mode = self.sr_mode();
+        if mode & 0b100 == 0 {
+            // shift in from CB2
+            self.sr = (self.sr << 1) | self.cb2 as u8;
+        } else {
+            // shift out onto CB2, rotating the byte
+            let bit = self.sr >> 7;
+            self.sr = (self.sr << 1) | bit;
+            self.cb2_out = bit != 0;
+        }
+
+        // free running shift out never stops and never interrupts
+        if mode == 0b100 {
+            return
+        }
+
+        self.sr_bits_left = self.sr_bits_left.saturating_sub(1);
+        if self.sr_bits_left == 0 {
+            self.set_flag(VIA_INT_SR);
+        }
+    }
+
+    fn start_shift(&mut self) {
+        self.clear_flag(VIA_INT_SR);
+        if self.sr_mode() != 0 {
+            self.sr_bits_left = 8;
+            self.sr_timer = self.sr_period().unwrap_or(0);
+        }
+    }
+
+    /// Drive CA1 from outside.
+    pub fn set_ca1(&mut self, level: bool) {
+        let active_rising = self.pcr & 0b0000_0001 != 0;
+        if level != self.ca1 && level == active_rising {
+            self.set_flag(VIA_INT_CA1);
+            self.ira_latch = self.port_a_pins();
+            if self.ca2_mode() == 0b100 {
+                self.ca2_out = true;
+            }
+        }
+        self.ca1 = level;
+    }
+
+    /// Drive CA2 from outside; only has an effect while CA2 is an input.
+    pub fn set_ca2(&mut self, level: bool) {
+        let mode = self.ca2_mode();
+        if mode & 0b100 == 0 {
+            let active_rising = mode & 0b010 != 0;
+            if level != self.ca2 && level == active_rising {
+                self.set_flag(VIA_INT_CA2);
+            }
+        }
+        self.ca2 = level;
+    }
+
+    /// Drive CB1 from outside. Also clocks the shift register in the external clock modes.
+    pub fn set_cb1(&mut self, level: bool) {
+        let active_rising = self.pcr & 0b0001_0000 != 0;
+        if level != self.cb1 && level == active_rising {
+            self.set_flag(VIA_INT_CB1);
+            self.irb_latch = self.port_b_pins();
+            if self.cb2_mode() == 0b100 {
+                self.cb2_out = true;
+            }
+        }
+        if level && !self.cb1 && matches!(self.sr_mode(), 0b011 | 0b111) && self.sr_bits_left > 0 {
+            self.shift();
+        }
+        self.cb1 = level;
+    }
+
+    /// Drive CB2 from outside; only has an effect while CB2 is an input.
+    pub fn set_cb2(&mut self, level: bool) {
+        let mode = self.cb2_mode();
+        if mode & 0b100 == 0 {
+            let active_rising = mode & 0b010 != 0;
+            if level != self.cb2 && level == active_rising {
+                self.set_flag(VIA_INT_CB2);
+            }
+        }
+        self.cb2 = level;
+    }
+
+    fn port_a_access(&mut self, handshake: bool) {
+        self.clear_flag(VIA_INT_CA1);
+        // "independent" interrupt modes aren't cleared by port accesses
+        if self.ca2_mode() & 0b101 != 0b001 {
+            self.clear_flag(VIA_INT_CA2);
+        }
+        if handshake {
+            match self.ca2_mode() {
+                0b100 | 0b101 => { self.ca2_out = false; }
+                _ => {}
+            }
+        }
+    }
+
+    fn port_b_access(&mut self, handshake: bool) {
+        self.clear_flag(VIA_INT_CB1);
+        if self.cb2_mode() & 0b101 != 0b001 {
+            self.clear_flag(VIA_INT_CB2);
+        }
+        if handshake {
+            match self.cb2_mode() {
+                0b100 | 0b101 => { self.cb2_out = false; }
+                _ => {}
+            }
+        }
+    }
+
+    fn update_control_outputs(&mut self) {
+        match self.ca2_mode() {
+            0b110 => { self.ca2_out = false; }
+            0b111 => { self.ca2_out = true; }
+            _ => {}
+        }
+        match self.cb2_mode() {
+            0b110 => { self.cb2_out = false; }
+            0b111 => { self.cb2_out = true; }
+            _ => {}
+        }
+    }
+
+    pub fn read_byte(&mut self, register: usize) -> u8 {
+        let value = self.peek_byte(register);
+        match register {
+            VIA_IORB => { self.port_b_access(false); }
+            VIA_IORA => { self.port_a_access(true); }
+            VIA_ORA_NH => {}
+            VIA_T1CL => { self.clear_flag(VIA_INT_T1); }
+            VIA_T2CL => { self.clear_flag(VIA_INT_T2); }
+            VIA_SR => { self.start_shift(); }
+            _ => {}
+        }
+        value
+    }
+
+    /// Reads a register without any of the side effects.
+    pub fn peek_byte(&self, register: usize) -> u8 {
+        match register {
+            VIA_IORB => {
+                let pins = if self.pb_latching() { self.irb_latch } else { self.port_b_pins() };
+                // output bits read back the output register, not the pins
+                (self.orb & self.ddrb) | (pins & !self.ddrb)
+            }
+            VIA_IORA | VIA_ORA_NH => {
+                if self.pa_latching() { self.ira_latch } else { self.port_a_pins() }
+            }
+            VIA_DDRB => self.ddrb,
+            VIA_DDRA => self.ddra,
+            VIA_T1CL => self.t1_counter as u8,
+            VIA_T1CH => (self.t1_counter >> 8) as u8,
+            VIA_T1LL => self.t1_latch as u8,
+            VIA_T1LH => (self.t1_latch >> 8) as u8,
+            VIA_T2CL => self.t2_counter as u8,
+            VIA_T2CH => (self.t2_counter >> 8) as u8,
+            VIA_SR => self.sr,
+            VIA_ACR => self.acr,
+            VIA_PCR => self.pcr,
+            VIA_IFR => self.ifr_with_any(),
+            VIA_IER => self.ier | 0x80,
+            _ => 0,
+        }
+    }
+
+    pub fn write_byte(&mut self, register: usize, data: u8) {
+        match register {
+            VIA_IORB => {
+                self.orb = data;
+                self.port_b_access(true);
+            }
+            VIA_IORA => {
+                self.ora = data;
+                self.port_a_access(true);
+            }
+            VIA_ORA_NH => { self.ora = data; }
+            VIA_DDRB => { self.ddrb = data; }
+            VIA_DDRA => { self.ddra = data; }
+            VIA_T1CL | VIA_T1LL => {
+                self.t1_latch = (self.t1_latch & 0xFF00) | data as u16;
+            }
+            VIA_T1CH => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (data as u16) << 8;
+                self.clear_flag(VIA_INT_T1);
+                self.t1_armed = true;
+                self.t1_reload = true;
+                if self.t1_pb7_enabled() {
+                    self.pb7 = false;
+                }
+            }
+            VIA_T1LH => {
+                self.t1_latch = (self.t1_latch & 0x00FF) | (data as u16) << 8;
+                self.clear_flag(VIA_INT_T1);
+            }
+            VIA_T2CL => { self.t2_latch_low = data; }
+            VIA_T2CH => {
+                self.t2_counter = (data as u16) << 8 | self.t2_latch_low as u16;
+                self.clear_flag(VIA_INT_T2);
+                self.t2_armed = true;
+                self.t2_reload = true;
+            }
+            VIA_SR => {
+                self.sr = data;
+                self.start_shift();
+            }
+            VIA_ACR => { self.acr = data; }
+            VIA_PCR => {
+                self.pcr = data;
+                self.update_control_outputs();
+            }
+            VIA_IFR => { self.clear_flag(data & 0x7F); }
+            VIA_IER => {
+                if data & 0x80 != 0 {
+                    self.ier |= data & 0x7F;
+                } else {
+                    self.ier &= !data;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Snapshot for Via {
+    fn save(&self, w: &mut StateWriter) {
+        for v in [self.orb, self.ora, self.ddrb, self.ddra, self.t2_latch_low, self.sr, self.sr_bits_left,
+                  self.acr, self.pcr, self.ifr, self.ier, self.port_a_in, self.port_b_in, self.ira_latch, self.irb_latch] {
+            w.u8(v);
+        }
+        for v in [self.t1_counter, self.t1_latch, self.t2_counter, self.sr_timer] {
+            w.u16(v);
+        }
+        for v in [self.t1_armed, self.t1_reload, self.pb7, self.t2_armed, self.t2_reload,
+                  self.ca1, self.ca2, self.cb1, self.cb2, self.ca2_out, self.cb2_out] {
+            w.bool(v);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        for v in [&mut self.orb, &mut self.ora, &mut self.ddrb, &mut self.ddra, &mut self.t2_latch_low, &mut self.sr, &mut self.sr_bits_left,
+                  &mut self.acr, &mut self.pcr, &mut self.ifr, &mut self.ier, &mut self.port_a_in, &mut self.port_b_in, &mut self.ira_latch, &mut self.irb_latch] {
+            *v = r.u8()?;
+        }
+        for v in [&mut self.t1_counter, &mut self.t1_latch, &mut self.t2_counter, &mut self.sr_timer] {
+            *v = r.u16()?;
+        }
+        for v in [&mut self.t1_armed, &mut self.t1_reload, &mut self.pb7, &mut self.t2_armed, &mut self.t2_reload,
+                  &mut self.ca1, &mut self.ca2, &mut self.cb1, &mut self.cb2, &mut self.ca2_out, &mut self.cb2_out] {
+            *v = r.bool()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,166 @@
+//! The VIA's timers and interrupt flags, and the flash cart's bank register hanging off port A.
+
+use crate::cartridges::CartridgeType;
+use crate::gametank_bus::CpuBus;
+use crate::gametank_bus::reg_system_control::*;
+use crate::gametank_bus::via::*;
+
+const ACR_T1_FREE_RUN: u8 = 0b0100_0000;
+const ACR_T1_PB7: u8 = 0b1000_0000;
+
+/// Starts T1 counting down from `latch`.
+fn start_t1(via: &mut Via, latch: u16) {
+    via.write_byte(VIA_T1CL, latch as u8);
+    via.write_byte(VIA_T1CH, (latch >> 8) as u8);
+}
+
+fn ticks(via: &mut Via, n: usize) {
+    for _ in 0..n {
+        via.tick();
+    }
+}
+
+fn t1_flagged(via: &Via) -> bool {
+    via.ifr & VIA_INT_T1 != 0
+}
+
+#[test]
+fn t1_one_shot_interrupts_once() {
+    let mut via = Via::default();
+    start_t1(&mut via, 5);
+
+    // one cycle to load the latch, then down through 0 to $FFFF
+    ticks(&mut via, 6);
+    assert!(!t1_flagged(&via), "t1 underflowed early, at ${:04X}", via.t1_counter);
+    via.tick();
+    assert!(t1_flagged(&via));
+    assert_eq!(via.t1_counter, 0xFFFF);
+
+    // it keeps counting, but doesn't interrupt again until it's rewritten
+    via.read_byte(VIA_T1CL);
+    ticks(&mut via, 0x10001);
+    assert!(!t1_flagged(&via), "one-shot t1 interrupted a second time");
+
+    start_t1(&mut via, 5);
+    ticks(&mut via, 7);
+    assert!(t1_flagged(&via), "rewriting t1 didn't rearm it");
+}
+
+#[test]
+fn t1_free_run_reloads_and_toggles_pb7() {
+    let mut via = Via::default();
+    via.write_byte(VIA_ACR, ACR_T1_FREE_RUN | ACR_T1_PB7);
+    start_t1(&mut via, 5);
+    assert_eq!(via.port_b_pins() & 0x80, 0, "starting t1 should pull pb7 low");
+
+    ticks(&mut via, 7);
+    assert!(t1_flagged(&via));
+    assert_eq!(via.port_b_pins() & 0x80, 0x80);
+
+    // the latch is reloaded, so it goes again every latch + 2 cycles
+    via.read_byte(VIA_T1CL);
+    via.tick();
+    assert_eq!(via.t1_counter, 5);
+    ticks(&mut via, 6);
+    assert!(t1_flagged(&via), "free-running t1 didn't interrupt again");
+    assert_eq!(via.port_b_pins() & 0x80, 0);
+}
+
+#[test]
+fn t1_flag_clears_on_read_and_on_write() {
+    let mut via = Via::default();
+    start_t1(&mut via, 0);
+    ticks(&mut via, 2);
+    assert!(t1_flagged(&via));
+
+    // reading the high byte doesn't count
+    via.read_byte(VIA_T1CH);
+    assert!(t1_flagged(&via));
+    via.read_byte(VIA_T1CL);
+    assert!(!t1_flagged(&via), "reading t1cl should clear the t1 flag");
+
+    start_t1(&mut via, 0);
+    ticks(&mut via, 2);
+    via.write_byte(VIA_T1LH, 0);
+    assert!(!t1_flagged(&via), "writing t1lh should clear the t1 flag");
+
+    // peeking has no side effects
+    start_t1(&mut via, 0);
+    ticks(&mut via, 2);
+    via.peek_byte(VIA_T1CL);
+    assert!(t1_flagged(&via));
+}
+
+#[test]
+fn ifr_clears_written_bits_only() {
+    let mut via = Via { ifr: VIA_INT_T1 | VIA_INT_T2 | VIA_INT_CA1, ..Via::default() };
+
+    via.write_byte(VIA_IFR, VIA_INT_T2);
+    assert_eq!(via.ifr, VIA_INT_T1 | VIA_INT_CA1);
+    // bit 7 can't be written, it's only ever the summary
+    via.write_byte(VIA_IFR, VIA_INT_ANY);
+    assert_eq!(via.ifr, VIA_INT_T1 | VIA_INT_CA1);
+    via.write_byte(VIA_IFR, 0x7F);
+    assert_eq!(via.ifr, 0);
+}
+
+#[test]
+fn irq_follows_enabled_flags() {
+    let mut via = Via::default();
+    start_t1(&mut via, 0);
+    ticks(&mut via, 2);
+    assert!(t1_flagged(&via));
+    assert!(!via.irq(), "a disabled interrupt shouldn't pull irq");
+    assert_eq!(via.read_byte(VIA_IFR), VIA_INT_T1);
+
+    // bit 7 set enables the written bits, clear disables them, and the rest are left alone
+    via.write_byte(VIA_IER, VIA_INT_ANY | VIA_INT_T1 | VIA_INT_CA1);
+    assert_eq!(via.read_byte(VIA_IER), VIA_INT_ANY | VIA_INT_T1 | VIA_INT_CA1);
+    assert!(via.irq());
+    assert_eq!(via.read_byte(VIA_IFR), VIA_INT_ANY | VIA_INT_T1);
+
+    via.write_byte(VIA_IER, VIA_INT_T1);
+    assert_eq!(via.read_byte(VIA_IER), VIA_INT_ANY | VIA_INT_CA1);
+    assert!(!via.irq());
+
+    via.write_byte(VIA_IER, VIA_INT_ANY | VIA_INT_T1);
+    assert!(via.irq());
+    via.read_byte(VIA_T1CL);
+    assert!(!via.irq(), "clearing the flag should release irq");
+    assert_eq!(via.read_byte(VIA_IFR), 0);
+}
+
+fn bank_register(bus: &CpuBus) -> (u8, u16) {
+    match &bus.cartridge {
+        CartridgeType::Cart2m(cart) => (cart.bank_shifter, cart.bank_mask),
+        _ => panic!("expected a 2M cartridge"),
+    }
+}
+
+/// Shifts `bank` out msb first, the way games do, then latches it with CS.
+fn shift_bank(bus: &mut CpuBus, bank: u8) {
+    for bit in (0..8).rev() {
+        let mosi = if (bank >> bit) & 1 != 0 { VIA_SPI_BIT_MOSI } else { 0 };
+        bus.write_byte(0x2801, mosi);
+        bus.write_byte(0x2801, mosi | VIA_SPI_BIT_CLK);
+    }
+    bus.write_byte(0x2801, 0);
+}
+
+#[test]
+fn port_a_clocks_the_flash_bank_register() {
+    let mut bus = CpuBus::with_seed(0);
+    bus.cartridge = CartridgeType::from_slice(&vec![0x91; 0x200000]).unwrap();
+    let (_, power_on_bank) = bank_register(&bus);
+    bus.write_byte(0x2803, VIA_SPI_BIT_CLK | VIA_SPI_BIT_MOSI | VIA_SPI_BIT_CS);
+
+    shift_bank(&mut bus, 0xA5);
+    assert_eq!(bank_register(&bus), (0xA5, power_on_bank), "the bank changed before cs latched it");
+    bus.write_byte(0x2801, VIA_SPI_BIT_CS);
+    assert_eq!(bank_register(&bus), (0xA5, 0xA5));
+
+    // an input pin floats high, so switching clk from a low output to an input clocks it too
+    bus.write_byte(0x2801, VIA_SPI_BIT_MOSI);
+    bus.write_byte(0x2803, VIA_SPI_BIT_MOSI | VIA_SPI_BIT_CS);
+    assert_eq!(bank_register(&bus).0, 0x4B);
+}
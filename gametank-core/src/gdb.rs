@@ -0,0 +1,395 @@
+//! A GDB remote serial protocol server for the main cpu, so external debuggers and scripts can drive the emulator.
+//!
+//! Registers are numbered a, x, y, s, p (8 bits each), then pc (16 bits, little endian).
+//! Everything is polled from the frontend's loop; the emulator keeps running on its own between polls.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use tracing::{debug, warn};
+use crate::debugger::{Breakpoint, HaltReason, Processor};
+use crate::emulator::{Emulator, PlayState};
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoint};
+
+const PACKET_SIZE: usize = 0x1000;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gametank.w65c02s">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="s" bitsize="8"/>
+    <reg name="p" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+enum Reply {
+    Send(String),
+    /// the stop reply is sent once the emulator halts
+    Later,
+    Disconnect,
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    no_ack: bool,
+    /// continuing or stepping, so owed a stop reply when the emulator halts
+    running: bool,
+}
+
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<Client>,
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn parse_hex_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+/// Parses `addr,len`. Addresses wider than the bus just wrap.
+fn parse_address_length(s: &str) -> Option<(u16, usize)> {
+    let (address, length) = s.split_once(',')?;
+    Some((u64::from_str_radix(address, 16).ok()? as u16, usize::from_str_radix(length, 16).ok()?))
+}
+
+fn registers(emulator: &Emulator) -> [u8; 7] {
+    let cpu = &emulator.cpu;
+    let [pcl, pch] = cpu.get_pc().to_le_bytes();
+    [cpu.get_a(), cpu.get_x(), cpu.get_y(), cpu.get_s(), cpu.get_p(), pcl, pch]
+}
+
+fn set_register(emulator: &mut Emulator, register: usize, value: &[u8]) -> bool {
+    let cpu = &mut emulator.cpu;
+    match (register, value) {
+        (0, [a]) => cpu.set_a(*a),
+        (1, [x]) => cpu.set_x(*x),
+        (2, [y]) => cpu.set_y(*y),
+        (3, [s]) => cpu.set_s(*s),
+        (4, [p]) => cpu.set_p(*p),
+        (5, [pcl, pch]) => cpu.set_pc(u16::from_le_bytes([*pcl, *pch])),
+        _ => return false,
+    }
+    true
+}
+
+fn stop_reply(emulator: &Emulator) -> String {
+    match emulator.debugger.halted {
+        Some(HaltReason::Breakpoint(..)) => "T05swbreak:;".into(),
+        Some(HaltReason::Watchpoint(_, _, hit)) => {
+            let kind = match hit.watchpoint.kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::ReadWrite => "awatch",
+            };
+            format!("T05{kind}:{:x};", hit.address)
+        }
+        Some(HaltReason::Interrupted(..)) => "S02".into(),
+        _ => "S05".into(),
+    }
+}
+
+impl Client {
+    fn send(&mut self, data: &str) -> std::io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        debug!(target: "gdb", "-> {data}");
+        self.stream.write_all(format!("${data}#{checksum:02x}").as_bytes())
+    }
+
+    /// Pulls complete packets out of the buffer, acking them as we go. Ctrl-C comes through as "\x03".
+    fn packets(&mut self) -> std::io::Result<Vec<String>> {
+        let mut packets = vec![];
+        loop {
+            let Some(start) = self.buffer.iter().position(|&b| b == b'$' || b == 0x03) else {
+                self.buffer.clear();
+                break
+            };
+            if self.buffer[start] == 0x03 {
+                self.buffer.drain(..=start);
+                packets.push("\x03".to_string());
+                continue
+            }
+
+            let Some(end) = self.buffer[start..].iter().position(|&b| b == b'#').map(|e| e + start) else {
+                break
+            };
+            if self.buffer.len() < end + 3 {
+                break
+            }
+
+            let data = String::from_utf8_lossy(&self.buffer[start + 1..end]).into_owned();
+            let checksum = std::str::from_utf8(&self.buffer[end + 1..end + 3]).ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok());
+            self.buffer.drain(..end + 3);
+
+            let valid = checksum == Some(data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b)));
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                packets.push(data);
+            }
+        }
+        Ok(packets)
+    }
+}
+
+impl GdbServer {
+    /// Listens on localhost only; there's no authentication, and memory writes can do anything the cpu can.
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        listener.set_nonblocking(true)?;
+        warn!("gdb server listening on {}", listener.local_addr()?);
+        Ok(Self { listener, client: None })
+    }
+
+    pub fn port(&self) -> Option<u16> {
+        self.listener.local_addr().ok().map(|a| a.port())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a connection, handles whatever the client has sent, and reports halts. Never blocks.
+    pub fn poll(&mut self, emulator: &mut Emulator) {
+        if self.client.is_none() {
+            self.accept(emulator);
+        }
+
+        if let Err(e) = self.service(emulator) {
+            warn!("gdb client disconnected: {e}");
+            self.client = None;
+            // nobody's left to continue it
+            emulator.resume();
+        }
+    }
+
+    fn accept(&mut self, emulator: &mut Emulator) {
+        match self.listener.accept() {
+            Ok((stream, address)) => {
+                warn!("gdb client connected from {address}");
+                if let Err(e) = stream.set_nonblocking(true).and_then(|_| stream.set_nodelay(true)) {
+                    warn!("couldn't set up gdb connection: {e}");
+                    return
+                }
+                // gdb expects the target to be stopped when it attaches
+                emulator.interrupt();
+                self.client = Some(Client { stream, buffer: vec![], no_ack: false, running: false });
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => { warn!("gdb accept failed: {e}"); }
+        }
+    }
+
+    fn service(&mut self, emulator: &mut Emulator) -> std::io::Result<()> {
+        let Some(client) = &mut self.client else {
+            return Ok(())
+        };
+
+        let mut chunk = [0u8; 1024];
+        loop {
+            match client.stream.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(n) => client.buffer.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        for packet in client.packets()? {
+            debug!(target: "gdb", "<- {packet}");
+            if packet == "\x03" {
+                if client.running {
+                    emulator.interrupt();
+                }
+                continue
+            }
+
+            match Self::handle(client, emulator, &packet) {
+                Reply::Send(reply) => client.send(&reply)?,
+                Reply::Later => {}
+                Reply::Disconnect => {
+                    warn!("gdb client detached");
+                    self.client = None;
+                    return Ok(())
+                }
+            }
+        }
+
+        if client.running && (emulator.debugger.is_halted() || emulator.play_state != PlayState::Playing) {
+            client.running = false;
+            client.send(&stop_reply(emulator))?;
+        }
+        Ok(())
+    }
+
+    /// An empty reply means "unsupported".
+    fn handle(client: &mut Client, emulator: &mut Emulator, packet: &str) -> Reply {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => stop_reply(emulator),
+            "g" => hex_bytes(&registers(emulator)),
+            "G" => {
+                let values = parse_hex_bytes(args).filter(|v| v.len() == 7);
+                match values {
+                    Some(v) => {
+                        let mut ok = true;
+                        for (register, range) in [(0, 0..1), (1, 1..2), (2, 2..3), (3, 3..4), (4, 4..5), (5, 5..7)] {
+                            ok &= set_register(emulator, register, &v[range]);
+                        }
+                        if ok { "OK".into() } else { "E01".into() }
+                    }
+                    None => "E01".into(),
+                }
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register @ 0..=4) => hex_bytes(&registers(emulator)[register..=register]),
+                Ok(5) => hex_bytes(&registers(emulator)[5..7]),
+                _ => "E01".into(),
+            },
+            "P" => {
+                let parsed = args.split_once('=')
+                    .and_then(|(r, v)| Some((usize::from_str_radix(r, 16).ok()?, parse_hex_bytes(v)?)));
+                match parsed {
+                    Some((register, value)) if set_register(emulator, register, &value) => "OK".into(),
+                    _ => "E01".into(),
+                }
+            }
+            "m" => match parse_address_length(args) {
+                Some((address, length)) => {
+                    let bytes: Vec<u8> = (0..length.min(PACKET_SIZE / 2))
+                        .map(|i| emulator.cpu_bus.peek_byte(address.wrapping_add(i as u16)))
+                        .collect();
+                    hex_bytes(&bytes)
+                }
+                None => "E01".into(),
+            },
+            "M" => {
+                let parsed = args.split_once(':')
+                    .and_then(|(range, data)| Some((parse_address_length(range)?, parse_hex_bytes(data)?)));
+                match parsed {
+                    Some(((address, length), data)) if data.len() == length => {
+                        // registers are skipped, since writing them would set things off
+                        let mut ok = true;
+                        for (i, byte) in data.into_iter().enumerate() {
+                            ok &= emulator.cpu_bus.poke_byte(address.wrapping_add(i as u16), byte);
+                        }
+                        if ok { "OK".into() } else { "E02".into() }
+                    }
+                    _ => "E01".into(),
+                }
+            }
+            "Z" | "z" => Self::handle_breakpoint(emulator, command == "Z", args),
+            "c" | "s" => {
+                if let Ok(address) = u64::from_str_radix(args, 16) {
+                    emulator.cpu.set_pc(address as u16);
+                }
+                if command == "c" {
+                    emulator.resume();
+                } else {
+                    emulator.step_into(Processor::Cpu);
+                }
+                client.running = true;
+                return Reply::Later
+            }
+            "D" => {
+                let _ = client.send("OK");
+                emulator.resume();
+                return Reply::Disconnect
+            }
+            "k" => {
+                emulator.resume();
+                return Reply::Disconnect
+            }
+            "H" | "T" => "OK".into(),
+            "q" | "Q" => Self::handle_query(client, packet),
+            _ => String::new(),
+        };
+        Reply::Send(reply)
+    }
+
+    fn handle_breakpoint(emulator: &mut Emulator, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(address), Some(length)) = (fields.next(), fields.next(), fields.next()) else {
+            return "E01".into()
+        };
+        let (Ok(address), Ok(length)) = (u64::from_str_radix(address, 16), u16::from_str_radix(length, 16)) else {
+            return "E01".into()
+        };
+        let address = address as u16;
+
+        let watch_kind = match kind {
+            "0" | "1" => {
+                let breakpoint = Breakpoint::new(Processor::Cpu, address);
+                if insert {
+                    emulator.debugger.breakpoints.insert(breakpoint);
+                } else {
+                    emulator.debugger.breakpoints.remove(&breakpoint);
+                }
+                return "OK".into()
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::ReadWrite,
+            _ => return String::new(),
+        };
+
+        for offset in 0..length.max(1) {
+            let watchpoint = Watchpoint {
+                target: WatchTarget::Cpu(address.wrapping_add(offset)),
+                kind: watch_kind,
+                value: None,
+            };
+            if insert {
+                emulator.add_watchpoint(watchpoint);
+            } else {
+                emulator.remove_watchpoint(&watchpoint);
+            }
+        }
+        "OK".into()
+    }
+
+    fn handle_query(client: &mut Client, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={PACKET_SIZE:x};qXfer:features:read+;QStartNoAckMode+;swbreak+")
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',')
+                .and_then(|(o, l)| Some((usize::from_str_radix(o, 16).ok()?, usize::from_str_radix(l, 16).ok()?))) else {
+                return "E01".into()
+            };
+            let chunk = TARGET_XML.get(offset.min(TARGET_XML.len())..).unwrap_or("");
+            return if chunk.len() > length {
+                format!("m{}", &chunk[..length])
+            } else {
+                format!("l{chunk}")
+            }
+        }
+
+        match packet {
+            "QStartNoAckMode" => {
+                client.no_ack = true;
+                "OK".into()
+            }
+            "qAttached" => "1".into(),
+            "qC" => "QC1".into(),
+            "qfThreadInfo" => "m1".into(),
+            "qsThreadInfo" => "l".into(),
+            _ => String::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,198 @@
+//! Talks to the server over a real loopback socket, the way gdb would.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use crate::debugger::{Breakpoint, Processor};
+use crate::emulator::{Emulator, PlayState};
+use crate::gdb::GdbServer;
+use crate::watchpoint::{WatchKind, WatchTarget, Watchpoint};
+
+struct Session {
+    server: GdbServer,
+    emulator: Emulator,
+    stream: TcpStream,
+    /// everything the server has sent, and not yet been looked at
+    received: Vec<u8>,
+}
+
+fn packet(data: &str) -> Vec<u8> {
+    let checksum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+    format!("${data}#{checksum:02x}").into_bytes()
+}
+
+impl Session {
+    fn connect() -> Self {
+        let mut server = GdbServer::bind(0).unwrap();
+        let mut emulator = Emulator::init_seeded(0);
+        emulator.play_state = PlayState::Playing;
+        let stream = TcpStream::connect(("127.0.0.1", server.port().unwrap())).unwrap();
+        stream.set_nonblocking(true).unwrap();
+
+        for _ in 0..1000 {
+            server.poll(&mut emulator);
+            if server.is_connected() {
+                return Self { server, emulator, stream, received: vec![] }
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("server never accepted the connection");
+    }
+
+    /// Polls the server until `done` is happy with what it's sent back.
+    fn poll_until(&mut self, done: impl Fn(&[u8]) -> bool) {
+        let mut chunk = [0u8; 4096];
+        for _ in 0..1000 {
+            self.server.poll(&mut self.emulator);
+            match self.stream.read(&mut chunk) {
+                Ok(n) => self.received.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => panic!("{e}"),
+            }
+            if done(&self.received) {
+                return
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        panic!("timed out, got {:?}", String::from_utf8_lossy(&self.received));
+    }
+
+    /// Takes the next reply packet, checking its checksum.
+    fn reply(&mut self) -> String {
+        let complete = |r: &[u8]| r.iter().position(|&b| b == b'#').is_some_and(|end| r.len() >= end + 3);
+        self.poll_until(complete);
+
+        let start = self.received.iter().position(|&b| b == b'$').expect("no packet start");
+        let end = self.received.iter().position(|&b| b == b'#').unwrap();
+        let data = String::from_utf8(self.received[start + 1..end].to_vec()).unwrap();
+        assert_eq!(self.received[end + 1..end + 3], packet(&data)[data.len() + 2..], "bad checksum on {data:?}");
+        self.received.drain(..end + 3);
+        data
+    }
+
+    /// Sends a packet, and checks the server acks it.
+    fn send(&mut self, data: &str) {
+        self.stream.write_all(&packet(data)).unwrap();
+        self.poll_until(|r| !r.is_empty());
+        assert_eq!(self.received.remove(0), b'+', "{data:?} wasn't acked");
+    }
+
+    fn exchange(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+}
+
+#[test]
+fn acks_good_packets_and_naks_bad_ones() {
+    let mut session = Session::connect();
+    assert!(session.emulator.debugger.is_halted(), "attaching should stop the emulator");
+
+    session.stream.write_all(b"$?#00").unwrap();
+    session.poll_until(|r| !r.is_empty());
+    assert_eq!(session.received, b"-");
+    session.received.clear();
+
+    assert_eq!(session.exchange("?"), "S02");
+    assert_eq!(session.exchange("vMustReplyEmpty"), "", "unknown packets get an empty reply");
+    assert_eq!(session.exchange("QStartNoAckMode"), "OK");
+    session.stream.write_all(&packet("?")).unwrap();
+    assert_eq!(session.reply(), "S02", "no-ack mode should send the reply without a +");
+}
+
+#[test]
+fn reads_and_writes_registers() {
+    let mut session = Session::connect();
+    assert_eq!(session.exchange("G0102030405cdab"), "OK");
+    let cpu = &session.emulator.cpu;
+    assert_eq!((cpu.get_a(), cpu.get_x(), cpu.get_y(), cpu.get_s(), cpu.get_p(), cpu.get_pc()), (1, 2, 3, 4, 5 | 0x20, 0xABCD));
+
+    // p has its always-set bit
+    assert_eq!(session.exchange("g"), "0102030425cdab");
+    assert_eq!(session.exchange("p5"), "cdab");
+    assert_eq!(session.exchange("P0=7f"), "OK");
+    assert_eq!(session.exchange("p0"), "7f");
+    assert_eq!(session.exchange("p6"), "E01");
+    assert_eq!(session.exchange("G0102"), "E01");
+}
+
+#[test]
+fn memory_writes_have_no_side_effects() {
+    let mut session = Session::connect();
+    assert_eq!(session.exchange("M200,3:a1b2c3"), "OK");
+    assert_eq!(session.exchange("m1ff,5"), format!("{:02x}a1b2c3{:02x}",
+        session.emulator.cpu_bus.peek_byte(0x1FF), session.emulator.cpu_bus.peek_byte(0x203)));
+
+    // a register can't be poked without setting it off, so it's refused and left alone
+    let banking = session.emulator.cpu_bus.system_control.banking_register.0;
+    assert_eq!(session.exchange("M2005,1:ff"), "E02");
+    assert_eq!(session.emulator.cpu_bus.system_control.banking_register.0, banking);
+
+    // and writes to the rom patch it, rather than talking to the flash chip
+    assert_eq!(session.exchange("Mfff0,1:42"), "OK");
+    assert_eq!(session.emulator.cpu_bus.peek_byte(0xFFF0), 0x42);
+
+    // nor do they trip watchpoints, or lose a hit that's still to be reported
+    session.emulator.add_watchpoint(Watchpoint { target: WatchTarget::Cpu(0x0200), kind: WatchKind::ReadWrite, value: None });
+    assert_eq!(session.exchange("M200,1:00"), "OK");
+    assert!(session.emulator.cpu_bus.watchpoints.hit.is_none());
+    session.emulator.cpu_bus.read_byte(0x0200);
+    assert_eq!(session.exchange("M200,1:00"), "OK");
+    assert!(session.emulator.cpu_bus.watchpoints.hit.is_some(), "a pending watchpoint hit was thrown away");
+
+    assert_eq!(session.exchange("M200,2:00"), "E01", "length and data disagree");
+}
+
+#[test]
+fn inserts_and_removes_breakpoints_and_watchpoints() {
+    let mut session = Session::connect();
+    let breakpoint = Breakpoint::new(Processor::Cpu, 0xC123);
+    assert_eq!(session.exchange("Z0,c123,1"), "OK");
+    assert!(session.emulator.debugger.breakpoints.contains(&breakpoint));
+    assert_eq!(session.exchange("z0,c123,1"), "OK");
+    assert!(session.emulator.debugger.breakpoints.is_empty());
+
+    assert_eq!(session.exchange("Z2,300,2"), "OK");
+    let targets: Vec<_> = session.emulator.watchpoints().iter().map(|w| (w.target, w.kind)).collect();
+    assert_eq!(targets, [(WatchTarget::Cpu(0x300), WatchKind::Write), (WatchTarget::Cpu(0x301), WatchKind::Write)]);
+    assert_eq!(session.exchange("z2,300,2"), "OK");
+    assert!(session.emulator.watchpoints().is_empty());
+
+    assert_eq!(session.exchange("Z9,300,1"), "", "unknown breakpoint types are unsupported");
+    assert_eq!(session.exchange("Z0,300"), "E01");
+}
+
+#[test]
+fn continues_and_steps() {
+    let mut session = Session::connect();
+    let pc = session.emulator.cpu.get_pc();
+
+    session.send("s");
+    assert!(!session.emulator.debugger.is_halted());
+    session.emulator.run_cycles(1000);
+    assert_eq!(session.reply(), "S05");
+    assert_ne!(session.emulator.cpu.get_pc(), pc, "step didn't run anything");
+
+    session.send("c");
+    assert!(!session.emulator.debugger.is_halted());
+    session.emulator.run_cycles(1000);
+    session.stream.write_all(b"\x03").unwrap();
+    assert_eq!(session.reply(), "S02", "ctrl-c should stop a continue");
+    assert!(session.emulator.debugger.is_halted());
+}
+
+#[test]
+fn dropping_the_connection_resumes() {
+    let mut session = Session::connect();
+    assert!(session.emulator.debugger.is_halted());
+    drop(session.stream);
+    for _ in 0..1000 {
+        session.server.poll(&mut session.emulator);
+        if !session.server.is_connected() {
+            break
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert!(!session.server.is_connected());
+    assert!(!session.emulator.debugger.is_halted(), "left halted with no client to continue it");
+}
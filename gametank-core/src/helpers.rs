@@ -11,13 +11,11 @@ pub fn get_now_ms() -> f64 {
     }
 
     #[cfg(not(target_arch = "wasm32"))]
-    unsafe {
+    {
+        use std::sync::OnceLock;
         use std::time::Instant;
-        static mut START_INSTANT: Option<Instant> = None;
+        static START_INSTANT: OnceLock<Instant> = OnceLock::new();
 
-        if START_INSTANT.is_none() {
-            START_INSTANT = Some(Instant::now());
-        }
-        return START_INSTANT.unwrap().elapsed().as_secs_f64() * 1000.0;
+        START_INSTANT.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
     }
 }
@@ -0,0 +1,34 @@
+//! Headless GameTank emulator core.
+//!
+//! Everything needed to run the machine (CPU + ACP, buses, blitter, cartridges) with no
+//! windowing, GPU or audio device dependencies. Frontends drive an [`Emulator`] and read the
+//! framebuffer / audio samples back out of it.
+#![allow(clippy::single_match, clippy::upper_case_acronyms, clippy::unusual_byte_groupings, clippy::identity_op)]
+#![allow(dead_code)]
+
+pub mod color_map;
+pub mod blitter;
+pub mod gamepad;
+pub mod gametank_bus;
+pub mod cartridges;
+pub mod emulator;
+pub mod input;
+pub mod helpers;
+pub mod savestate;
+pub mod rewind;
+pub mod wav;
+pub mod audio_capture;
+pub mod audio_output;
+pub mod pacing;
+pub mod debugger;
+pub mod watchpoint;
+pub mod symbols;
+pub mod disassembler;
+pub mod gdb;
+pub mod trace;
+
+pub use emulator::{Emulator, PlayState};
+pub use audio_output::AudioOutput;
+pub use gametank_bus::{AcpBus, CpuBus};
+pub use blitter::Blitter;
+pub use cartridges::{CartridgeType, RomError};
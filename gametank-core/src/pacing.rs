@@ -0,0 +1,41 @@
+//! Audio-driven pacing. The wall clock and the sound card's clock never quite agree, so pacing off
+//! `get_now_ms()` alone slowly over- or under-fills the audio buffer until it crackles. With
+//! `audio_pacing` on, each tick runs the wall clock's worth of cycles nudged toward keeping
+//! [`TARGET_LATENCY`] of audio queued, and the output's resampling ratio gets nudged the same way
+//! to soak up whatever's left.
+
+use crate::emulator::{Emulator, CYCLES_PER_FRAME};
+
+/// how much audio to keep queued up, in seconds
+pub const TARGET_LATENCY: f64 = 0.05;
+/// how much of the buffer's error to make up each tick, by running more or fewer cycles
+const CYCLE_GAIN: f64 = 0.1;
+/// the most the output's rate gets adjusted, which is well under what anyone can hear
+const MAX_RATE_ADJUSTMENT: f64 = 0.005;
+
+impl Emulator {
+    /// Cycles to run for `elapsed_cycles` of wall-clock time, nudged to keep the audio buffer
+    /// at [`TARGET_LATENCY`]. Outputs that don't play in real time leave it untouched.
+    pub(crate) fn paced_cycles(&mut self, elapsed_cycles: i32) -> i32 {
+        if !self.audio_pacing {
+            return elapsed_cycles
+        }
+        let Some(audio) = &mut self.audio_out else {
+            return elapsed_cycles
+        };
+        let Some(queued) = audio.queued_seconds() else {
+            return elapsed_cycles
+        };
+
+        // positive when the buffer's running low
+        let error = (TARGET_LATENCY - queued) / TARGET_LATENCY;
+        audio.set_rate_adjustment(1.0 + error.clamp(-1.0, 1.0) * MAX_RATE_ADJUSTMENT);
+
+        let correction = (TARGET_LATENCY - queued) * CYCLE_GAIN * self.cpu_frequency_hz;
+        // catch up by at most a frame per tick, so a stall can't turn into a burst of fast-forward
+        (elapsed_cycles + correction as i32).clamp(0, elapsed_cycles + CYCLES_PER_FRAME)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,93 @@
+//! Nudging the cycle count and the output's rate toward the target latency.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::rc::Rc;
+use crate::AudioOutput;
+use crate::audio_output::NullAudioOutput;
+use crate::emulator::Emulator;
+use crate::pacing::{MAX_RATE_ADJUSTMENT, TARGET_LATENCY};
+
+/// A real-time output whose buffer is however full the test says it is.
+#[derive(Clone, Default)]
+struct FakeOutput {
+    queued: Rc<Cell<f64>>,
+    rate_adjustment: Rc<Cell<Option<f64>>>,
+}
+
+impl AudioOutput for FakeOutput {
+    fn push_sample(&mut self, _sample: u8, _sample_rate: f64) {}
+
+    fn queued_seconds(&self) -> Option<f64> {
+        Some(self.queued.get())
+    }
+
+    fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.rate_adjustment.set(Some(ratio));
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+const ELAPSED: i32 = 10_000;
+
+fn paced() -> (Emulator, FakeOutput) {
+    let mut emulator = Emulator::init_seeded(0);
+    let output = FakeOutput::default();
+    emulator.audio_out = Some(Box::new(output.clone()));
+    emulator.audio_pacing = true;
+    (emulator, output)
+}
+
+#[test]
+fn runs_more_when_the_buffer_is_low_and_less_when_its_high() {
+    let (mut emulator, output) = paced();
+
+    output.queued.set(TARGET_LATENCY);
+    assert_eq!(emulator.paced_cycles(ELAPSED), ELAPSED, "on target, but still nudged");
+    assert_eq!(output.rate_adjustment.get(), Some(1.0));
+
+    output.queued.set(TARGET_LATENCY / 2.0);
+    let low = emulator.paced_cycles(ELAPSED);
+    assert!(low > ELAPSED, "a draining buffer got {low} cycles");
+    assert!(output.rate_adjustment.get().unwrap() > 1.0, "a draining buffer should play slower");
+
+    output.queued.set(TARGET_LATENCY * 1.5);
+    let high = emulator.paced_cycles(ELAPSED);
+    assert!(high < ELAPSED, "a filling buffer got {high} cycles");
+    assert!(output.rate_adjustment.get().unwrap() < 1.0, "a filling buffer should play faster");
+
+    // an empty buffer catches up faster than a half empty one
+    output.queued.set(0.0);
+    assert!(emulator.paced_cycles(ELAPSED) > low);
+}
+
+#[test]
+fn rate_adjustment_stays_inaudible() {
+    let (mut emulator, output) = paced();
+    for queued in [0.0, TARGET_LATENCY / 4.0, TARGET_LATENCY * 3.0, 10.0] {
+        output.queued.set(queued);
+        let cycles = emulator.paced_cycles(ELAPSED);
+        let ratio = output.rate_adjustment.get().unwrap();
+        assert!((ratio - 1.0).abs() <= MAX_RATE_ADJUSTMENT + f64::EPSILON, "{queued}s queued adjusted the rate by {ratio}");
+        assert!(cycles >= 0, "{queued}s queued asked for {cycles} cycles");
+    }
+    output.queued.set(10.0);
+    assert_eq!(emulator.paced_cycles(ELAPSED), 0, "a very full buffer should just wait");
+}
+
+#[test]
+fn leaves_the_clock_alone_without_pacing() {
+    let (mut emulator, output) = paced();
+    output.queued.set(0.0);
+    emulator.audio_pacing = false;
+    assert_eq!(emulator.paced_cycles(ELAPSED), ELAPSED);
+    assert_eq!(output.rate_adjustment.get(), None);
+
+    // nor can it pace off an output that doesn't play in real time
+    emulator.audio_pacing = true;
+    emulator.audio_out = Some(Box::new(NullAudioOutput));
+    assert_eq!(emulator.paced_cycles(ELAPSED), ELAPSED);
+}
@@ -0,0 +1,192 @@
+use std::collections::VecDeque;
+
+/// 64MiB is a little over a minute of history for most games
+pub const DEFAULT_REWIND_BUDGET: usize = 64 * 1024 * 1024;
+
+/// History of per-frame save states, kept as a chain of compressed deltas.
+///
+/// Only the newest state is kept whole. Each delta is the xor between a state and the one after it,
+/// run-length encoded (most of the machine doesn't change from one frame to the next), so walking
+/// backwards is just xor-ing deltas into the newest state one at a time. States don't all have to be
+/// the same size, since a cartridge's flash only gets saved once it's been written to.
+///
+/// Off until it's given a budget, since snapshotting every frame isn't free.
+pub struct RewindBuffer {
+    budget: usize,
+    current: Vec<u8>,
+    deltas: VecDeque<Vec<u8>>,
+    used: usize,
+}
+
+impl Default for RewindBuffer {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl RewindBuffer {
+    /// `budget` is in bytes, and includes the newest full state. A budget of 0 disables rewinding.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            current: Vec::new(),
+            deltas: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        if budget == 0 {
+            self.clear();
+        }
+        self.evict();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.budget > 0
+    }
+
+    /// How many frames back we can currently go.
+    pub fn frames(&self) -> usize {
+        self.deltas.len()
+    }
+
+    /// Bytes currently held, including the newest full state.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.current.clear();
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    /// Adds the newest state. Loading a different cartridge should [`RewindBuffer::clear`] first.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if !self.enabled() {
+            return
+        }
+
+        if self.current.is_empty() {
+            self.used = state.len();
+            self.current = state;
+            return
+        }
+
+        let delta = xor_rle_encode(&self.current, &state);
+        self.used = self.used + delta.len() + state.len() - self.current.len();
+        self.deltas.push_back(delta);
+        self.current = state;
+        self.evict();
+    }
+
+    /// Steps one frame back, returning the state to load, or `None` when the history is exhausted.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        self.used -= delta.len();
+        self.used -= self.current.len();
+        xor_rle_apply(&delta, &mut self.current);
+        self.used += self.current.len();
+        Some(&self.current)
+    }
+
+    fn evict(&mut self) {
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => { self.used -= delta.len(); }
+                None => break,
+            }
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = bytes[*pos];
+        *pos += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return v
+        }
+        shift += 7;
+    }
+}
+
+/// Encodes `a ^ b` as `a`'s length, then a sequence of (zero run length, literal length, literals).
+/// The shorter of the two is padded out with zeros.
+fn xor_rle_encode(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, a.len());
+
+    let len = a.len().max(b.len());
+    let (a, b) = (padded(a, len), padded(b, len));
+    let mut i = 0;
+    while i < a.len() {
+        let zeros_start = i;
+        while i < a.len() && a[i] == b[i] {
+            i += 1;
+        }
+        if i == a.len() {
+            break
+        }
+
+        // a lone matching byte isn't worth ending a literal run over
+        let literal_start = i;
+        while i < a.len() && (a[i] != b[i] || (i + 1 < a.len() && a[i + 1] != b[i + 1])) {
+            i += 1;
+        }
+
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend(a[literal_start..i].iter().zip(&b[literal_start..i]).map(|(x, y)| x ^ y));
+    }
+    out
+}
+
+fn padded(bytes: &[u8], len: usize) -> std::borrow::Cow<'_, [u8]> {
+    if bytes.len() == len {
+        bytes.into()
+    } else {
+        let mut v = bytes.to_vec();
+        v.resize(len, 0);
+        v.into()
+    }
+}
+
+/// Turns `b` back into `a`, given their [`xor_rle_encode`]d delta.
+fn xor_rle_apply(delta: &[u8], target: &mut Vec<u8>) {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    target.resize(target.len().max(len), 0);
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literals = read_varint(delta, &mut pos);
+        for (t, d) in target[i..i + literals].iter_mut().zip(&delta[pos..pos + literals]) {
+            *t ^= d;
+        }
+        i += literals;
+        pos += literals;
+    }
+    target.truncate(len);
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,37 @@
+use crate::rewind::RewindBuffer;
+
+/// A fake state: a shared prefix that changes a little each frame, and a tail that can grow or shrink,
+/// like a cartridge's flash once it's saved to.
+fn state(frame: u8, tail: usize) -> Vec<u8> {
+    let mut state = vec![0xAA; 64];
+    state[frame as usize % 64] = frame;
+    state.extend((0..tail).map(|i| i as u8 ^ frame));
+    state
+}
+
+#[test]
+fn rewinds_across_size_changes() {
+    let mut rewind = RewindBuffer::new(1024 * 1024);
+    let states: Vec<Vec<u8>> = [0, 0, 16, 16, 48, 8, 0, 32].iter().enumerate()
+        .map(|(frame, &tail)| state(frame as u8, tail))
+        .collect();
+    for s in &states {
+        rewind.push(s.clone());
+    }
+    assert_eq!(rewind.frames(), states.len() - 1);
+
+    for expected in states.iter().rev().skip(1) {
+        assert_eq!(rewind.pop(), Some(expected.as_slice()));
+    }
+    assert_eq!(rewind.pop(), None);
+    assert_eq!(rewind.used(), states[0].len());
+}
+
+#[test]
+fn off_by_default() {
+    let mut rewind = RewindBuffer::default();
+    rewind.push(state(0, 0));
+    rewind.push(state(1, 0));
+    assert!(!rewind.enabled());
+    assert_eq!(rewind.pop(), None);
+}
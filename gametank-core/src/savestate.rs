@@ -0,0 +1,472 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use w65c02s::{State, System, W65C02S, P_I};
+use crate::cartridges::CartridgeType;
+use crate::cartridges::cart2m::{FlashCommand, FLASH_SECTORS, FLASH_SECTOR_SIZE};
+use crate::emulator::Emulator;
+use crate::gamepad::GamePad;
+use crate::gametank_bus::{AcpBus, Bus, CpuBus};
+
+const MAGIC: &[u8; 4] = b"GTSS";
+
+/// Bump this whenever the layout written by [`Snapshot::save`] changes.
+pub const SAVE_STATE_VERSION: u32 = 5;
+
+#[derive(Debug)]
+pub enum SaveStateError {
+    Io(std::io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    /// the state goes on after everything it should contain
+    TrailingBytes(usize),
+    /// a field holds a value the emulator never writes
+    Corrupt(&'static str),
+    CartridgeMismatch,
+    RomMismatch,
+    EmptySlot(usize),
+}
+
+impl Display for SaveStateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveStateError::Io(e) => write!(f, "i/o error: {e}"),
+            SaveStateError::BadMagic => write!(f, "not a GameTank save state"),
+            SaveStateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {v} (expected {SAVE_STATE_VERSION})"),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::TrailingBytes(n) => write!(f, "save state has {n} unexpected bytes at the end"),
+            SaveStateError::Corrupt(what) => write!(f, "save state is corrupt: bad {what}"),
+            SaveStateError::CartridgeMismatch => write!(f, "save state was made with a different cartridge type"),
+            SaveStateError::RomMismatch => write!(f, "save state was made with a different rom"),
+            SaveStateError::EmptySlot(slot) => write!(f, "nothing saved in slot {slot}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+impl From<std::io::Error> for SaveStateError {
+    fn from(e: std::io::Error) -> Self {
+        SaveStateError::Io(e)
+    }
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    pub bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn u8(&mut self, v: u8) { self.bytes.push(v); }
+    pub fn bool(&mut self, v: bool) { self.bytes.push(v as u8); }
+    pub fn u16(&mut self, v: u16) { self.bytes.extend_from_slice(&v.to_le_bytes()); }
+    pub fn u32(&mut self, v: u32) { self.bytes.extend_from_slice(&v.to_le_bytes()); }
+    pub fn i32(&mut self, v: i32) { self.bytes.extend_from_slice(&v.to_le_bytes()); }
+    pub fn u64(&mut self, v: u64) { self.bytes.extend_from_slice(&v.to_le_bytes()); }
+    pub fn slice(&mut self, v: &[u8]) { self.bytes.extend_from_slice(v); }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.bytes.len() < n {
+            return Err(SaveStateError::Truncated)
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, SaveStateError> { Ok(self.take(1)?[0]) }
+    pub fn bool(&mut self) -> Result<bool, SaveStateError> { Ok(self.u8()? != 0) }
+    pub fn u16(&mut self) -> Result<u16, SaveStateError> { Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap())) }
+    pub fn u32(&mut self) -> Result<u32, SaveStateError> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    pub fn i32(&mut self) -> Result<i32, SaveStateError> { Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    pub fn u64(&mut self) -> Result<u64, SaveStateError> { Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) }
+    pub fn slice(&mut self, out: &mut [u8]) -> Result<(), SaveStateError> {
+        out.copy_from_slice(self.take(out.len())?);
+        Ok(())
+    }
+
+    /// Checks that everything has been read.
+    pub fn finish(&self) -> Result<(), SaveStateError> {
+        match self.bytes.len() {
+            0 => Ok(()),
+            n => Err(SaveStateError::TrailingBytes(n)),
+        }
+    }
+}
+
+/// Something that can be written into / restored from a save state.
+pub trait Snapshot {
+    fn save(&self, w: &mut StateWriter);
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+impl Snapshot for Emulator {
+    fn save(&self, w: &mut StateWriter) {
+        self.cpu.save(w);
+        self.acp.save(w);
+        self.cpu_bus.save(w);
+        self.acp_bus.save(w);
+        self.blitter.save(w);
+        w.i32(self.clock_cycles_to_vblank);
+        w.i32(self.acp_cycle_accumulator);
+        w.u64(self.frame_count);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.cpu.load(r)?;
+        self.acp.load(r)?;
+        self.cpu_bus.load(r)?;
+        self.acp_bus.load(r)?;
+        self.blitter.load(r)?;
+        self.clock_cycles_to_vblank = r.i32()?;
+        self.acp_cycle_accumulator = r.i32()?;
+        self.frame_count = r.u64()?;
+
+        // states are taken between instructions, so there are never any cycles left to account for
+        self.cpu_bus.clear_cycles();
+        self.acp_bus.clear_cycles();
+        Ok(())
+    }
+}
+
+impl Snapshot for CpuBus {
+    fn save(&self, w: &mut StateWriter) {
+        let sc = &self.system_control;
+        w.u8(sc.reset_acp);
+        w.u8(sc.nmi_acp);
+        w.u8(sc.banking_register.0);
+        sc.via.save(w);
+        w.u8(sc.audio_enable_sample_rate);
+        w.u8(sc.dma_flags.0);
+        for gamepad in &sc.gamepads {
+            gamepad.save(w);
+        }
+
+        let b = &self.blitter;
+        for v in [b.vx, b.vy, b.gx, b.gy, b.width, b.height, b.start.write, b.color] {
+            w.u8(v);
+        }
+        w.bool(b.start.addressed);
+
+        for bank in self.ram_banks.iter() {
+            w.slice(bank);
+        }
+        for fb in &self.framebuffers {
+            w.slice(&fb.borrow()[..]);
+        }
+        for bank in self.vram_banks.iter() {
+            w.slice(bank);
+        }
+
+        // aram only ever lives on the acp bus while the acp is mid-run
+        w.slice(self.aram.as_deref().map_or(&[0; 0x1000], |aram| aram));
+
+        self.cartridge.save(w);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        let sc = &mut self.system_control;
+        sc.reset_acp = r.u8()?;
+        sc.nmi_acp = r.u8()?;
+        sc.banking_register.0 = r.u8()?;
+        sc.via.load(r)?;
+        sc.audio_enable_sample_rate = r.u8()?;
+        sc.dma_flags.0 = r.u8()?;
+        for gamepad in sc.gamepads.iter_mut() {
+            gamepad.load(r)?;
+        }
+
+        let b = &mut self.blitter;
+        for v in [&mut b.vx, &mut b.vy, &mut b.gx, &mut b.gy, &mut b.width, &mut b.height, &mut b.start.write, &mut b.color] {
+            *v = r.u8()?;
+        }
+        b.start.addressed = r.bool()?;
+
+        for bank in self.ram_banks.iter_mut() {
+            r.slice(bank)?;
+        }
+        for fb in &self.framebuffers {
+            r.slice(&mut fb.borrow_mut()[..])?;
+        }
+        for bank in self.vram_banks.iter_mut() {
+            r.slice(bank)?;
+        }
+        // everything changed, as far as the vram viewer is concerned
+        self.vram_quad_written = [true; 32];
+
+        let mut aram = Box::new([0; 0x1000]);
+        r.slice(&mut aram[..])?;
+        self.aram = Some(aram);
+
+        self.cartridge.load(r)
+    }
+}
+
+impl Snapshot for GamePad {
+    fn save(&self, w: &mut StateWriter) {
+        for v in [self.up, self.down, self.left, self.right, self.b, self.a, self.c, self.start, self.port_select] {
+            w.bool(v);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        for v in [&mut self.up, &mut self.down, &mut self.left, &mut self.right, &mut self.b, &mut self.a, &mut self.c, &mut self.start, &mut self.port_select] {
+            *v = r.bool()?;
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for AcpBus {
+    fn save(&self, w: &mut StateWriter) {
+        w.i32(self.irq_counter);
+        w.u8(self.sample);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.irq_counter = r.i32()?;
+        self.sample = r.u8()?;
+        Ok(())
+    }
+}
+
+/// Which kind of cartridge a state was saved with, so it can be checked before anything's loaded.
+fn cartridge_tag(cartridge: &CartridgeType) -> u8 {
+    match cartridge {
+        CartridgeType::Cart8k(_) => 0,
+        CartridgeType::Cart32k(_) => 1,
+        CartridgeType::Cart2m(_) => 2,
+    }
+}
+
+/// Only the cartridge's mutable state is saved, the ROM itself is expected to already be loaded.
+/// The cartridge's type is in the state's header.
+impl Snapshot for CartridgeType {
+    fn save(&self, w: &mut StateWriter) {
+        match self {
+            CartridgeType::Cart8k(_) | CartridgeType::Cart32k(_) => {}
+            CartridgeType::Cart2m(c) => {
+                w.u8(c.bank_shifter);
+                w.u16(c.bank_mask);
+                w.u8(c.flash_command as u8);
+                // flash is part of the machine too, but only the sectors that differ from the rom
+                for sector in 0..FLASH_SECTORS {
+                    w.bool(c.modified_sectors[sector]);
+                    if c.modified_sectors[sector] {
+                        w.slice(c.sector(sector));
+                    }
+                }
+            }
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        match self {
+            CartridgeType::Cart8k(_) | CartridgeType::Cart32k(_) => {}
+            CartridgeType::Cart2m(c) => {
+                c.bank_shifter = r.u8()?;
+                c.bank_mask = r.u16()?;
+                c.flash_command = match r.u8()? {
+                    0 => FlashCommand::Read,
+                    1 => FlashCommand::Unlock1,
+                    2 => FlashCommand::Unlock2,
+                    3 => FlashCommand::Program,
+                    4 => FlashCommand::EraseSetup,
+                    5 => FlashCommand::EraseUnlock1,
+                    6 => FlashCommand::EraseUnlock2,
+                    7 => FlashCommand::Autoselect,
+                    _ => return Err(SaveStateError::Corrupt("flash command")),
+                };
+                // sectors written since the state was saved go back to the rom's contents
+                let mut sector_data = vec![0; FLASH_SECTOR_SIZE];
+                for sector in 0..FLASH_SECTORS {
+                    if r.bool()? {
+                        r.slice(&mut sector_data)?;
+                        c.restore_sector(sector, Some(&sector_data));
+                    } else {
+                        c.restore_sector(sector, None);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// w65c02s keeps its interrupt latches private, and the derived Debug output is the only window into them.
+/// That's why the crate's pinned to an exact version; if its Debug output ever changes, this panics instead of
+/// quietly saving the wrong thing, and `cpu_snapshot_keeps_interrupt_latches` fails.
+fn cpu_flag(debug: &str, field: &str) -> bool {
+    if debug.contains(&format!(" {field}: true")) {
+        return true
+    }
+    assert!(debug.contains(&format!(" {field}: false")), "w65c02s' Debug output has no {field} field: {debug}");
+    false
+}
+
+impl Snapshot for W65C02S {
+    fn save(&self, w: &mut StateWriter) {
+        w.u8(match self.get_state() {
+            State::HasBeenReset => 0,
+            State::Running => 1,
+            State::AwaitingInterrupt => 2,
+            State::Stopped => 3,
+        });
+        w.u16(self.get_pc());
+        for v in [self.get_a(), self.get_x(), self.get_y(), self.get_s(), self.get_p()] {
+            w.u8(v);
+        }
+
+        let debug = format!("{self:?}");
+        for field in ["irq", "irq_pending", "nmi", "nmi_edge", "nmi_pending"] {
+            w.bool(cpu_flag(&debug, field));
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        let state = r.u8()?;
+        let pc = r.u16()?;
+        let (a, x, y, s, p) = (r.u8()?, r.u8()?, r.u8()?, r.u8()?, r.u8()?);
+        let (irq, irq_pending, nmi, nmi_edge, nmi_pending) = (r.bool()?, r.bool()?, r.bool()?, r.bool()?, r.bool()?);
+
+        // there are no setters for the cpu's state or interrupt latches, so we walk a fresh cpu
+        // into the right state by feeding it instructions from a scratch bus
+        let mut cpu = W65C02S::new();
+        let mut scratch = ScratchBus { opcode: 0xEA };
+        cpu.step(&mut scratch); // reset -> running
+
+        if nmi && !nmi_edge {
+            // an nmi that has already been taken: raise it, let a nop latch it, then service it
+            cpu.set_nmi(true);
+            cpu.step(&mut scratch);
+            cpu.step(&mut scratch);
+        }
+
+        match state {
+            0 | 1 => {
+                // latch the pending flags at the end of a nop
+                cpu.set_p(if irq_pending { 0 } else { P_I });
+                cpu.set_irq(irq_pending);
+                if nmi_pending {
+                    cpu.set_nmi(true);
+                }
+                cpu.step(&mut scratch);
+            }
+            // nothing is ever pending while waiting, and nothing pending matters once stopped
+            2 => {
+                scratch.opcode = 0xCB; // WAI
+                cpu.step(&mut scratch);
+            }
+            3 => {
+                scratch.opcode = 0xDB; // STP
+                cpu.step(&mut scratch);
+            }
+            _ => return Err(SaveStateError::Corrupt("cpu state")),
+        }
+
+        if nmi_edge && !cpu_flag(&format!("{cpu:?}"), "nmi_edge") {
+            cpu.set_nmi(true);
+        }
+        if !nmi {
+            cpu.set_nmi(false);
+        }
+        cpu.set_irq(irq);
+
+        if state == 0 {
+            cpu.reset();
+        }
+
+        cpu.set_pc(pc);
+        cpu.set_a(a);
+        cpu.set_x(x);
+        cpu.set_y(y);
+        cpu.set_s(s);
+        cpu.set_p(p);
+
+        *self = cpu;
+        Ok(())
+    }
+}
+
+/// Serves the same opcode from every address, and swallows writes.
+struct ScratchBus {
+    opcode: u8,
+}
+
+impl System for ScratchBus {
+    fn read(&mut self, _: &mut W65C02S, _: u16) -> u8 {
+        self.opcode
+    }
+
+    fn write(&mut self, _: &mut W65C02S, _: u16, _: u8) {}
+}
+
+impl Emulator {
+    /// Serializes the whole machine, minus the cartridge ROM, into the versioned save state format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::default();
+        w.slice(MAGIC);
+        w.u32(SAVE_STATE_VERSION);
+        w.u64(self.rom_hash);
+        w.u8(cartridge_tag(&self.cpu_bus.cartridge));
+        Snapshot::save(self, &mut w);
+        w.bytes
+    }
+
+    /// Restores a save state made by [`Emulator::save_state`] on top of the currently loaded cartridge.
+    /// On error the machine is left exactly as it was.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), SaveStateError> {
+        let mut r = StateReader::new(bytes);
+        let mut magic = [0; 4];
+        r.slice(&mut magic).map_err(|_| SaveStateError::BadMagic)?;
+        if &magic != MAGIC {
+            return Err(SaveStateError::BadMagic)
+        }
+
+        let version = r.u32()?;
+        if version != SAVE_STATE_VERSION {
+            return Err(SaveStateError::UnsupportedVersion(version))
+        }
+        let rom_hash = r.u64()?;
+        if r.u8()? != cartridge_tag(&self.cpu_bus.cartridge) {
+            return Err(SaveStateError::CartridgeMismatch)
+        }
+        if rom_hash != self.rom_hash {
+            return Err(SaveStateError::RomMismatch)
+        }
+
+        // a truncated or corrupt state only shows itself partway through, so keep what it's replacing
+        let backup = self.save_state();
+        let result = Snapshot::load(self, &mut r).and_then(|()| r.finish());
+        if result.is_err() {
+            self.load_state(&backup).expect("a state that was just saved should load");
+        }
+        result
+    }
+
+    pub fn save_state_to_file(&self, path: &Path) -> Result<(), SaveStateError> {
+        File::create(path)?.write_all(&self.save_state())?;
+        Ok(())
+    }
+
+    pub fn load_state_from_file(&mut self, path: &Path) -> Result<(), SaveStateError> {
+        let mut bytes = Vec::new();
+        File::open(path)?.read_to_end(&mut bytes)?;
+        self.load_state(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,174 @@
+//! Save states have to put the machine back exactly as it was, whatever happened in between.
+
+use crate::cartridges::CartridgeType;
+use crate::cartridges::cart2m::{Cartridge2M, FLASH_SECTORS, FLASH_SECTOR_SIZE};
+use crate::emulator::Emulator;
+use crate::savestate::{SaveStateError, ScratchBus, Snapshot, StateReader, StateWriter};
+use w65c02s::W65C02S;
+
+/// also ends up as the reset vector, $9191
+const ROM_FILL: u8 = 0x91;
+
+fn with_2m_cart() -> Emulator {
+    let mut emulator = Emulator::init_seeded(0);
+    emulator.load_rom(&vec![ROM_FILL; 128 * 0x4000]).unwrap();
+    emulator
+}
+
+fn cart(emulator: &mut Emulator) -> &mut Cartridge2M {
+    match &mut emulator.cpu_bus.cartridge {
+        CartridgeType::Cart2m(cart) => cart,
+        _ => panic!("expected a 2M cartridge"),
+    }
+}
+
+#[test]
+fn loading_restores_flash_exactly() {
+    let mut emulator = with_2m_cart();
+    cart(&mut emulator).load_save_sector(3, &[0x22; FLASH_SECTOR_SIZE]);
+    let state = emulator.save_state();
+
+    // written after the state was saved
+    cart(&mut emulator).load_save_sector(3, &[0x44; FLASH_SECTOR_SIZE]);
+    cart(&mut emulator).load_save_sector(5, &[0x33; FLASH_SECTOR_SIZE]);
+    cart(&mut emulator).unsaved = false;
+
+    emulator.load_state(&state).unwrap();
+    let cart = cart(&mut emulator);
+    assert!(cart.sector(3).iter().all(|&b| b == 0x22), "sector saved in the state wasn't restored");
+    assert!(cart.sector(5).iter().all(|&b| b == ROM_FILL), "sector written after the state kept its contents");
+    assert!(cart.modified_sectors[3] && !cart.modified_sectors[5]);
+    assert!(!cart.unsaved, "loading a state counted as a flash write");
+}
+
+#[test]
+fn rejected_states_leave_the_machine_alone() {
+    let mut emulator = with_2m_cart();
+    let state = emulator.save_state();
+    emulator.run_frame();
+    let before = emulator.save_state();
+
+    let mut other_cart = Emulator::init_seeded(0);
+    assert!(matches!(emulator.load_state(&other_cart.save_state()), Err(SaveStateError::CartridgeMismatch)));
+    assert!(matches!(other_cart.load_state(&state), Err(SaveStateError::CartridgeMismatch)));
+
+    assert!(matches!(emulator.load_state(&state[..state.len() - 1]), Err(SaveStateError::Truncated)));
+    let mut trailing = state.clone();
+    trailing.push(0);
+    assert!(matches!(emulator.load_state(&trailing), Err(SaveStateError::TrailingBytes(1))));
+
+    // the cartridge is followed by the acp bus, the blitter and three counters, and ends with its 32 sector flags
+    let mut after_cartridge = StateWriter::default();
+    emulator.acp_bus.save(&mut after_cartridge);
+    emulator.blitter.save(&mut after_cartridge);
+    let mut corrupt = state.clone();
+    let flash_command = corrupt.len() - (after_cartridge.bytes.len() + 16) - FLASH_SECTORS - 1;
+    corrupt[flash_command] = 0xFF;
+    assert!(matches!(emulator.load_state(&corrupt), Err(SaveStateError::Corrupt(_))));
+
+    assert!(emulator.save_state() == before, "a rejected state changed the machine");
+    emulator.load_state(&state).unwrap();
+}
+
+#[test]
+fn states_only_load_into_the_same_rom() {
+    let mut emulator = with_2m_cart();
+    let state = emulator.save_state();
+
+    let mut other_game = vec![ROM_FILL; 128 * 0x4000];
+    other_game[0] = 0;
+    emulator.load_rom(&other_game).unwrap();
+    assert!(matches!(emulator.load_state(&state), Err(SaveStateError::RomMismatch)));
+
+    // writing to flash doesn't make it a different game
+    emulator.load_rom(&vec![ROM_FILL; 128 * 0x4000]).unwrap();
+    cart(&mut emulator).load_save_sector(0, &[0x22; FLASH_SECTOR_SIZE]);
+    emulator.load_state(&state).unwrap();
+}
+
+#[test]
+fn quick_saves_outlive_the_emulator() {
+    let dir = std::env::temp_dir().join(format!("gametank-quick-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rom = dir.join("game.gtr");
+    std::fs::write(&rom, vec![ROM_FILL; 128 * 0x4000]).unwrap();
+
+    let mut emulator = Emulator::init_seeded(0);
+    emulator.load_rom_file(&rom).unwrap();
+    emulator.run_frame();
+    emulator.quick_save(2);
+    let state = emulator.save_state();
+    assert!(dir.join("game.ss2").exists());
+
+    let mut restarted = Emulator::init_seeded(1);
+    restarted.load_rom_file(&rom).unwrap();
+    assert!(matches!(restarted.quick_load(1), Err(SaveStateError::EmptySlot(1))));
+    restarted.quick_load(2).unwrap();
+    assert!(restarted.save_state() == state);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A state saved at any instruction boundary has to carry on exactly like the machine it came from,
+/// interrupt latches and all. The cpu's latches are scraped out of w65c02s' Debug output, so this is
+/// also what catches that format changing.
+#[test]
+fn cpu_round_trip_runs_identically() {
+    let mut original = Emulator::init_seeded(0);
+    let mut saw_waiting = false;
+
+    // a few frames, so vblank nmis and the game's waits for them are in there
+    for checkpoint in 0..24 {
+        for _ in 0..5_003 {
+            original.step_instruction();
+        }
+        saw_waiting |= original.cpu.get_state() == w65c02s::State::AwaitingInterrupt;
+
+        let state = original.save_state();
+        let mut copy = Emulator::init_seeded(1);
+        copy.load_state(&state).unwrap();
+        assert_eq!((copy.cpu, copy.acp), (original.cpu, original.acp), "checkpoint {checkpoint}: cpus differ after loading");
+        assert!(copy.save_state() == state, "checkpoint {checkpoint}: state changed just by loading it");
+
+        let mut ahead = Emulator::init_seeded(2);
+        ahead.load_state(&state).unwrap();
+        for instruction in 0..3_000 {
+            original.step_instruction();
+            ahead.step_instruction();
+            assert_eq!(
+                (original.cpu.get_pc(), original.acp.get_pc()), (ahead.cpu.get_pc(), ahead.acp.get_pc()),
+                "checkpoint {checkpoint}: diverged {instruction} instructions after loading"
+            );
+        }
+        assert!(original.save_state() == ahead.save_state(), "checkpoint {checkpoint}: states differ after running on");
+    }
+    assert!(saw_waiting, "never saved while the cpu was waiting for an interrupt");
+}
+
+/// Walks a cpu through every combination of interrupt lines around an instruction, and checks each
+/// one comes back from a snapshot with all its private latches intact.
+#[test]
+fn cpu_snapshot_keeps_interrupt_latches() {
+    for bits in 0..64u8 {
+        let [irq_enabled, irq_before, nmi_before, irq_after, nmi_after, wait] = std::array::from_fn(|i| bits & (1 << i) != 0);
+
+        let mut cpu = W65C02S::new();
+        let mut bus = ScratchBus { opcode: 0xEA }; // NOP
+        cpu.step(&mut bus);
+        cpu.set_p(if irq_enabled { 0 } else { 0x04 });
+        cpu.set_irq(irq_before);
+        cpu.set_nmi(nmi_before);
+        if wait {
+            bus.opcode = 0xCB; // WAI
+        }
+        cpu.step(&mut bus);
+        cpu.set_irq(irq_after);
+        cpu.set_nmi(nmi_after);
+
+        let mut w = StateWriter::default();
+        cpu.save(&mut w);
+        let mut loaded = W65C02S::new();
+        loaded.load(&mut StateReader::new(&w.bytes)).unwrap();
+        assert_eq!(loaded, cpu, "case {bits:06b}");
+    }
+}
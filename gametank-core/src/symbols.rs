@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+/// The 2M cart's switchable bank window. Everything else is fixed, so symbols there have no bank.
+const BANK_WINDOW: std::ops::Range<u16> = 0x8000..0xC000;
+const BANK_SIZE: u32 = 0x4000;
+
+#[derive(Debug)]
+pub enum SymbolError {
+    Io(std::io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl Display for SymbolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SymbolError::Io(e) => write!(f, "couldn't read symbol file: {e}"),
+            SymbolError::Parse { line, message } => write!(f, "line {line}: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl From<std::io::Error> for SymbolError {
+    fn from(e: std::io::Error) -> Self {
+        SymbolError::Io(e)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u16,
+    /// the 2M cart bank this lives in, for code in the $8000-$BFFF window
+    pub bank: Option<u8>,
+}
+
+#[derive(Clone, Debug)]
+struct SourceLine {
+    start: u16,
+    size: u16,
+    bank: Option<u8>,
+    file: usize,
+    line: u32,
+    /// ld65's line type: 0 is assembly, 1 is C, 2 is a macro expansion
+    kind: u8,
+}
+
+impl SourceLine {
+    fn contains(&self, address: u16) -> bool {
+        address.wrapping_sub(self.start) < self.size
+    }
+}
+
+/// Symbols and source lines for the program running on the cpu, from ld65's `--dbgfile` or a VICE label file.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+    by_address: HashMap<u16, Vec<usize>>,
+    files: Vec<String>,
+    lines: Vec<SourceLine>,
+}
+
+fn matches_bank(symbol_bank: Option<u8>, bank: Option<u8>) -> bool {
+    symbol_bank.is_none() || bank.is_none() || symbol_bank == bank
+}
+
+/// The bank a rom file offset ends up in, if `address` is in the banked window.
+fn bank_of(address: u16, rom_offset: Option<u32>) -> Option<u8> {
+    let offset = rom_offset?;
+    BANK_WINDOW.contains(&address).then_some((offset / BANK_SIZE) as u8)
+}
+
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Splits the `key=value,key="value"` part of a `.dbg` line, respecting quotes.
+fn parse_fields(fields: &str) -> HashMap<&str, &str> {
+    let mut map = HashMap::new();
+    let mut rest = fields;
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = if let Some(quoted) = after.strip_prefix('"') {
+            let end = quoted.find('"').unwrap_or(quoted.len());
+            (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+        } else {
+            after.split_once(',').map_or((after, ""), |(v, n)| (v, n))
+        };
+        map.insert(key.trim(), value);
+        rest = next.strip_prefix(',').unwrap_or(next);
+    }
+    map
+}
+
+impl SymbolTable {
+    /// Loads `.dbg` files as ld65 debug info, and anything else as VICE labels.
+    pub fn load(path: &Path) -> Result<Self, SymbolError> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("dbg") => Self::from_ld65_dbg(&text),
+            _ => Self::from_vice_labels(&text),
+        }
+    }
+
+    pub fn from_ld65_dbg(text: &str) -> Result<Self, SymbolError> {
+        struct Segment { start: u32, rom_offset: Option<u32> }
+        struct Span { seg: u32, start: u32, size: u32 }
+
+        let mut table = SymbolTable::default();
+        let mut segments = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut file_ids = HashMap::new();
+        let mut pending_lines = vec![];
+        let mut pending_symbols = vec![];
+
+        for (n, line) in text.lines().enumerate() {
+            let error = |message: &str| SymbolError::Parse { line: n + 1, message: message.to_string() };
+            let Some((kind, fields)) = line.split_once(char::is_whitespace) else {
+                continue
+            };
+            let fields = parse_fields(fields.trim());
+            let number = |key: &str| fields.get(key).and_then(|v| parse_number(v));
+            let id = || number("id").ok_or_else(|| error("missing id"));
+
+            match kind {
+                "version" if number("major") != Some(2) => {
+                    return Err(error("only version 2 debug info is supported"))
+                }
+                "file" => {
+                    file_ids.insert(id()?, table.files.len());
+                    table.files.push(fields.get("name").unwrap_or(&"?").to_string());
+                }
+                "seg" => {
+                    let start = number("start").ok_or_else(|| error("segment without a start"))?;
+                    segments.insert(id()?, Segment { start, rom_offset: number("ooffs") });
+                }
+                "span" => {
+                    let (Some(seg), Some(start), Some(size)) = (number("seg"), number("start"), number("size")) else {
+                        return Err(error("incomplete span"))
+                    };
+                    spans.insert(id()?, Span { seg, start, size });
+                }
+                "line" => {
+                    let (Some(file), Some(line)) = (number("file"), number("line")) else {
+                        return Err(error("incomplete line"))
+                    };
+                    let kind = number("type").unwrap_or(0) as u8;
+                    for span in fields.get("span").into_iter().flat_map(|s| s.split('+')) {
+                        let span = parse_number(span).ok_or_else(|| error("bad span id"))?;
+                        pending_lines.push((file, line, kind, span));
+                    }
+                }
+                "sym" => {
+                    // equates are usually just constants, and imports are defined in some other module's record
+                    if fields.get("type") != Some(&"lab") {
+                        continue
+                    }
+                    let Some(value) = number("val") else {
+                        continue
+                    };
+                    let name = fields.get("name").ok_or_else(|| error("symbol without a name"))?;
+                    pending_symbols.push((name.to_string(), value, number("seg")));
+                }
+                _ => {}
+            }
+        }
+
+        for (name, value, seg) in pending_symbols {
+            let address = value as u16;
+            let rom_offset = seg.and_then(|s| segments.get(&s))
+                .and_then(|s| Some(s.rom_offset? + value.checked_sub(s.start)?));
+            table.insert(Symbol { name, address, bank: bank_of(address, rom_offset) });
+        }
+
+        for (file, line, kind, span) in pending_lines {
+            let (Some(span), Some(&file)) = (spans.get(&span), file_ids.get(&file)) else {
+                continue
+            };
+            let Some(seg) = segments.get(&span.seg) else {
+                continue
+            };
+            let start = (seg.start + span.start) as u16;
+            let rom_offset = seg.rom_offset.map(|o| o + span.start);
+            table.lines.push(SourceLine {
+                start,
+                size: span.size as u16,
+                bank: bank_of(start, rom_offset),
+                file,
+                line,
+                kind,
+            });
+        }
+
+        Ok(table)
+    }
+
+    /// Parses `al C:E000 .main` style lines, as written by ld65's `-Ln`. Addresses above $FFFF
+    /// are taken to carry the bank in their upper bits.
+    pub fn from_vice_labels(text: &str) -> Result<Self, SymbolError> {
+        let mut table = SymbolTable::default();
+
+        for (n, line) in text.lines().enumerate() {
+            let error = |message: &str| SymbolError::Parse { line: n + 1, message: message.to_string() };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("al") => {}
+                // other vice monitor commands (breakpoints etc) can live in the same file
+                _ => continue,
+            }
+            let (Some(address), Some(name)) = (words.next(), words.next()) else {
+                return Err(error("expected an address and a label"))
+            };
+            let address = address.split_once(':').map_or(address, |(_, a)| a);
+            let address = u32::from_str_radix(address, 16).map_err(|_| error("bad address"))?;
+            let bank = (address > 0xFFFF).then_some((address >> 16) as u8);
+
+            table.insert(Symbol {
+                name: name.trim_start_matches('.').to_string(),
+                address: address as u16,
+                bank,
+            });
+        }
+
+        Ok(table)
+    }
+
+    fn insert(&mut self, symbol: Symbol) {
+        self.by_address.entry(symbol.address).or_default().push(self.symbols.len());
+        self.symbols.push(symbol);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty() && self.lines.is_empty()
+    }
+
+    pub fn symbols(&self) -> &[Symbol] {
+        &self.symbols
+    }
+
+    /// The best name for `address` with `bank` mapped in. Cheap locals (`@loop`) only win if there's nothing else.
+    pub fn label(&self, address: u16, bank: Option<u8>) -> Option<&str> {
+        let mut candidates = self.by_address.get(&address)?.iter()
+            .map(|&i| &self.symbols[i])
+            .filter(|s| matches_bank(s.bank, bank));
+        let first = candidates.next()?;
+        let best = std::iter::once(first).chain(candidates)
+            .find(|s| !s.name.starts_with('@'))
+            .unwrap_or(first);
+        Some(&best.name)
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// The source file and line that generated the code at `address`.
+    pub fn source_line(&self, address: u16, bank: Option<u8>) -> Option<(&str, u32)> {
+        self.lines.iter()
+            .filter(|l| l.contains(address) && matches_bank(l.bank, bank))
+            // prefer real source over macro expansions, then the tightest span
+            .min_by_key(|l| (l.kind == 2, l.size))
+            .map(|l| (self.files[l.file].as_str(), l.line))
+    }
+}
+
+#[cfg(test)]
+mod tests;
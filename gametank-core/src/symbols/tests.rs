@@ -0,0 +1,87 @@
+//! Reading ld65 debug info and VICE labels, and looking names and lines back up.
+
+use crate::symbols::*;
+
+/// A cut down ld65 `--dbgfile`: the fixed bank at $C000, and bank 3 of the rom in the $8000 window.
+const DBG: &str = "\
+version\tmajor=2,minor=0
+info\tcsym=0,file=1,lib=0,line=3,mod=1,scope=1,seg=2,span=4,sym=5,type=0
+file\tid=0,name=\"src/main,v=2.asm\",size=420,mtime=0x65000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x00C000,size=0x0100,addrsize=absolute,type=ro,oname=\"game.gtr\",ooffs=0x1FC000
+seg\tid=1,name=\"BANK3\",start=0x008000,size=0x0100,addrsize=absolute,type=ro,oname=\"game.gtr\",ooffs=0x00C000
+span\tid=0,seg=0,start=0,size=3
+span\tid=1,seg=1,start=16,size=2
+span\tid=2,seg=0,start=1,size=1
+span\tid=3,seg=0,start=3,size=3
+line\tid=0,file=0,line=12,span=0
+line\tid=1,file=0,line=40,type=2,span=2+3
+line\tid=2,file=0,line=7,span=1
+sym\tid=0,name=\"@loop\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab
+sym\tid=1,name=\"reset\",addrsize=absolute,scope=0,def=0,val=0xC000,seg=0,type=lab
+sym\tid=2,name=\"@skip\",addrsize=absolute,scope=0,def=0,val=0xC003,seg=0,type=lab
+sym\tid=3,name=\"draw_sprite\",addrsize=absolute,scope=0,def=0,val=0x8010,seg=1,type=lab
+sym\tid=4,name=\"SCREEN_W\",addrsize=zeropage,scope=0,def=0,val=0x80,type=equ
+";
+
+#[test]
+fn reads_ld65_debug_info() {
+    let table = SymbolTable::from_ld65_dbg(DBG).unwrap();
+    let names: Vec<_> = table.symbols().iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["@loop", "reset", "@skip", "draw_sprite"], "equates aren't labels");
+
+    // only the window at $8000 is banked, and the bank comes from where the segment sits in the rom
+    assert_eq!(table.find("reset"), Some(&Symbol { name: "reset".into(), address: 0xC000, bank: None }));
+    assert_eq!(table.find("draw_sprite"), Some(&Symbol { name: "draw_sprite".into(), address: 0x8010, bank: Some(3) }));
+}
+
+#[test]
+fn labels_prefer_real_names_in_the_right_bank() {
+    let table = SymbolTable::from_ld65_dbg(DBG).unwrap();
+    assert_eq!(table.label(0xC000, None), Some("reset"), "a cheap local beat the real label");
+    assert_eq!(table.label(0xC003, Some(5)), Some("@skip"), "a cheap local is better than nothing");
+    assert_eq!(table.label(0xC001, None), None);
+
+    assert_eq!(table.label(0x8010, Some(3)), Some("draw_sprite"));
+    assert_eq!(table.label(0x8010, Some(4)), None, "the label belongs to another bank");
+    assert_eq!(table.label(0x8010, None), Some("draw_sprite"), "no cart bank should match any");
+}
+
+#[test]
+fn source_lines_prefer_real_source_and_respect_banks() {
+    let table = SymbolTable::from_ld65_dbg(DBG).unwrap();
+    // quoted fields keep their commas and equals signs
+    assert_eq!(table.source_line(0xC001, None), Some(("src/main,v=2.asm", 12)), "the macro expansion won, for having a tighter span");
+    assert_eq!(table.source_line(0xC005, None), Some(("src/main,v=2.asm", 40)));
+    assert_eq!(table.source_line(0xC006, None), None);
+
+    assert_eq!(table.source_line(0x8011, Some(3)), Some(("src/main,v=2.asm", 7)));
+    assert_eq!(table.source_line(0x8011, Some(2)), None);
+}
+
+#[test]
+fn rejects_bad_debug_info() {
+    let error = SymbolTable::from_ld65_dbg("version\tmajor=1,minor=0\n").unwrap_err();
+    assert!(matches!(error, SymbolError::Parse { line: 1, .. }), "{error}");
+
+    let error = SymbolTable::from_ld65_dbg("version\tmajor=2,minor=0\nspan\tid=0,seg=0,size=3\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: incomplete span");
+}
+
+#[test]
+fn reads_vice_labels() {
+    let labels = "al C:E000 .reset\nbreak C:E000\nal 03A010 .draw_sprite\nal C:E000 .@loop\n";
+    let table = SymbolTable::from_vice_labels(labels).unwrap();
+
+    assert_eq!(table.symbols(), [
+        Symbol { name: "reset".into(), address: 0xE000, bank: None },
+        Symbol { name: "draw_sprite".into(), address: 0xA010, bank: Some(3) },
+        Symbol { name: "@loop".into(), address: 0xE000, bank: None },
+    ]);
+    assert_eq!(table.label(0xE000, None), Some("reset"));
+    assert_eq!(table.label(0xA010, Some(2)), None);
+
+    let error = SymbolTable::from_vice_labels("al C:E000 .reset\nal C:E003\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: expected an address and a label");
+    let error = SymbolTable::from_vice_labels("al C:XYZ .reset\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 1: bad address");
+}
@@ -0,0 +1,291 @@
+//! Per-instruction execution traces, for diffing runs against each other or against real hardware logs.
+//!
+//! Text traces are one line per instruction, with the registers and banking as they were before it ran.
+//! Binary traces start with `GTTR` and a version byte, then have a 16 byte record per instruction:
+//!
+//! | offset | contents                                                      |
+//! |--------|---------------------------------------------------------------|
+//! | 0      | cycle count, u32 little endian (wraps after ~20 minutes)      |
+//! | 4      | pc, u16 little endian                                         |
+//! | 6      | opcode and operand bytes, padded to 3                         |
+//! | 9      | a, x, y, s, p                                                 |
+//! | 14     | banking register ($2005), or 0 for the acp                    |
+//! | 15     | bit 7 set for the acp; bits 0-6 are the 2M cart bank          |
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::warn;
+use w65c02s::State;
+use crate::cartridges::CartridgeType;
+use crate::debugger::Processor;
+use crate::disassembler::disassemble;
+use crate::emulator::Emulator;
+
+const BINARY_MAGIC: &[u8; 4] = b"GTTR";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TraceFormat::Text),
+            "binary" => Ok(TraceFormat::Binary),
+            _ => Err(format!("unknown trace format {s:?}, expected text or binary")),
+        }
+    }
+}
+
+/// When to start or stop tracing.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceTrigger {
+    /// the processor is about to execute the instruction at this address
+    Address(Processor, u16),
+    /// the frame counter has reached this frame
+    Frame(u64),
+}
+
+impl Display for TraceTrigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TraceTrigger::Address(processor, address) => write!(f, "{processor}:${address:04X}"),
+            TraceTrigger::Frame(frame) => write!(f, "frame:{frame}"),
+        }
+    }
+}
+
+/// Parses `frame:120`, `cpu:E000`, `acp:$0200`, or a bare cpu address like `$E000`.
+impl FromStr for TraceTrigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.trim().split_once(':').unwrap_or(("cpu", s.trim()));
+        let address = || u16::from_str_radix(value.trim_start_matches('$'), 16)
+            .map_err(|_| format!("bad address in trigger {s:?}"));
+
+        match kind {
+            "frame" => value.parse().map(TraceTrigger::Frame).map_err(|_| format!("bad frame in trigger {s:?}")),
+            "cpu" => Ok(TraceTrigger::Address(Processor::Cpu, address()?)),
+            "acp" => Ok(TraceTrigger::Address(Processor::Acp, address()?)),
+            _ => Err(format!("unknown trigger {s:?}, expected frame:N, cpu:ADDR or acp:ADDR")),
+        }
+    }
+}
+
+pub struct Tracer {
+    pub format: TraceFormat,
+    pub cpu: bool,
+    pub acp: bool,
+    pub start: Option<TraceTrigger>,
+    pub stop: Option<TraceTrigger>,
+
+    out: Box<dyn Write>,
+    /// cycles each processor has run since tracing was set up
+    cycles: [u64; 2],
+    started: bool,
+    stopped: bool,
+}
+
+impl Tracer {
+    pub fn new(out: impl Write + 'static, format: TraceFormat) -> std::io::Result<Self> {
+        let mut out: Box<dyn Write> = Box::new(out);
+        if format == TraceFormat::Binary {
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&[BINARY_VERSION])?;
+        }
+        Ok(Self {
+            format,
+            cpu: true,
+            acp: false,
+            start: None,
+            stop: None,
+            out,
+            cycles: [0; 2],
+            started: false,
+            stopped: false,
+        })
+    }
+
+    pub fn create(path: &Path, format: TraceFormat) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), format)
+    }
+
+    /// Traces just `processors`, between the `start` and `stop` triggers.
+    pub fn with_options(self, processors: &[Processor], start: Option<TraceTrigger>, stop: Option<TraceTrigger>) -> Self {
+        Self {
+            cpu: processors.contains(&Processor::Cpu),
+            acp: processors.contains(&Processor::Acp),
+            start,
+            stop,
+            ..self
+        }
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.started && !self.stopped
+    }
+
+    fn traces(&self, processor: Processor) -> bool {
+        match processor {
+            Processor::Cpu => self.cpu,
+            Processor::Acp => self.acp,
+        }
+    }
+
+    fn triggered(trigger: Option<TraceTrigger>, processor: Processor, pc: u16, frame: u64) -> bool {
+        match trigger {
+            Some(TraceTrigger::Address(p, address)) => p == processor && address == pc,
+            Some(TraceTrigger::Frame(f)) => frame >= f,
+            None => false,
+        }
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// The `--trace` options, for frontends to flatten into their own.
+#[cfg(feature = "clap")]
+#[derive(clap::Args, Debug)]
+pub struct TraceArgs {
+    /// write an execution trace to this file
+    #[arg(long)]
+    pub trace: Option<std::path::PathBuf>,
+
+    /// text, or binary for long traces
+    #[arg(long, default_value = "text", requires = "trace")]
+    pub trace_format: TraceFormat,
+
+    /// which processors to trace, e.g. cpu,acp
+    #[arg(long, value_delimiter = ',', default_value = "cpu", requires = "trace")]
+    pub trace_processors: Vec<Processor>,
+
+    /// start tracing at frame:N, cpu:ADDR or acp:ADDR, instead of right away
+    #[arg(long, requires = "trace")]
+    pub trace_start: Option<TraceTrigger>,
+
+    /// stop tracing at frame:N, cpu:ADDR or acp:ADDR
+    #[arg(long, requires = "trace")]
+    pub trace_stop: Option<TraceTrigger>,
+}
+
+#[cfg(feature = "clap")]
+impl TraceArgs {
+    /// The tracer these options ask for, if any.
+    pub fn tracer(&self) -> Result<Option<Tracer>, String> {
+        let Some(path) = &self.trace else {
+            return Ok(None)
+        };
+        let tracer = Tracer::create(path, self.trace_format).map_err(|e| format!("couldn't create {}: {e}", path.display()))?;
+        Ok(Some(tracer.with_options(&self.trace_processors, self.trace_start, self.trace_stop)))
+    }
+}
+
+impl Emulator {
+    /// Called before `processor` executes an instruction.
+    pub(crate) fn trace_instruction(&mut self, processor: Processor) {
+        let Some(tracer) = &self.tracer else {
+            return
+        };
+        if tracer.stopped {
+            return
+        }
+
+        let pc = self.pc(processor);
+        let frame = self.frame_count;
+        let tracer = self.tracer.as_mut().unwrap();
+        if !tracer.started {
+            tracer.started = tracer.start.is_none() || Tracer::triggered(tracer.start, processor, pc, frame);
+            if tracer.started {
+                warn!("trace started at {processor} ${pc:04X}, frame {frame}");
+            }
+        } else if Tracer::triggered(tracer.stop, processor, pc, frame) {
+            warn!("trace stopped at {processor} ${pc:04X}, frame {frame}");
+            tracer.stopped = true;
+            let _ = tracer.flush();
+            return
+        }
+        if !tracer.started || !tracer.traces(processor) {
+            return
+        }
+
+        let cpu = match processor {
+            Processor::Cpu => &self.cpu,
+            Processor::Acp => &self.acp,
+        };
+        // nothing's executing while it waits for an interrupt
+        if cpu.get_state() != State::Running {
+            return
+        }
+
+        let instruction = disassemble(pc, |address| self.peek(processor, address));
+        let registers = [cpu.get_a(), cpu.get_x(), cpu.get_y(), cpu.get_s(), cpu.get_p()];
+        let (banking, cart_bank) = match processor {
+            Processor::Cpu => {
+                let cart_bank = match &self.cpu_bus.cartridge {
+                    CartridgeType::Cart2m(cart) => Some((cart.bank_mask & 0x7F) as u8),
+                    _ => None,
+                };
+                (Some(self.cpu_bus.system_control.banking_register.0), cart_bank)
+            }
+            Processor::Acp => (None, None),
+        };
+
+        let tracer = self.tracer.as_mut().unwrap();
+        let cycles = tracer.cycles[processor as usize];
+        let result = match tracer.format {
+            TraceFormat::Text => {
+                let bytes: Vec<String> = instruction.bytes[..instruction.size() as usize].iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect();
+                let [a, x, y, s, p] = registers;
+                let mut line = format!(
+                    "{processor} {cycles:>10} {pc:04X}  {:<8}  {:<16} A:{a:02X} X:{x:02X} Y:{y:02X} S:{s:02X} P:{p:02X}",
+                    bytes.join(" "), instruction.to_string()
+                );
+                if let Some(banking) = banking {
+                    line += &format!(" BK:{banking:02X}");
+                }
+                if let Some(bank) = cart_bank {
+                    line += &format!(" CB:{bank:02X}");
+                }
+                writeln!(tracer.out, "{line}")
+            }
+            TraceFormat::Binary => {
+                let mut record = [0u8; 16];
+                record[0..4].copy_from_slice(&(cycles as u32).to_le_bytes());
+                record[4..6].copy_from_slice(&pc.to_le_bytes());
+                record[6..9].copy_from_slice(&instruction.bytes);
+                record[9..14].copy_from_slice(&registers);
+                record[14] = banking.unwrap_or(0);
+                record[15] = cart_bank.unwrap_or(0) & 0x7F | if processor == Processor::Acp { 0x80 } else { 0 };
+                tracer.out.write_all(&record)
+            }
+        };
+
+        if let Err(e) = result {
+            warn!("stopping trace, couldn't write it: {e}");
+            tracer.stopped = true;
+        }
+    }
+
+    /// Called after `processor` executes an instruction that took `cycles`.
+    pub(crate) fn trace_cycles(&mut self, processor: Processor, cycles: i32) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.cycles[processor as usize] += cycles as u64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,133 @@
+//! What ends up in a trace, in both formats, and when tracing starts and stops.
+
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+use crate::debugger::Processor;
+use crate::emulator::Emulator;
+use crate::trace::{TraceFormat, TraceTrigger, Tracer};
+
+/// `LDA #$12; LDX #$34; loop: INX; JMP loop`, at $E000 with every vector pointing at it.
+fn rom() -> Vec<u8> {
+    let mut rom = vec![0xEA; 0x2000];
+    rom[..8].copy_from_slice(&[0xA9, 0x12, 0xA2, 0x34, 0xE8, 0x4C, 0x04, 0xE0]);
+    for vector in [0x1FFA, 0x1FFC, 0x1FFE] {
+        rom[vector..vector + 2].copy_from_slice(&[0x00, 0xE0]);
+    }
+    rom
+}
+
+/// Somewhere to write a trace that can still be read once the tracer has it.
+#[derive(Clone, Default)]
+struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+/// An emulator that's been through the reset vector, and is about to run the rom's first instruction.
+fn traced(format: TraceFormat, start: Option<TraceTrigger>, stop: Option<TraceTrigger>) -> (Emulator, Output) {
+    let mut emulator = Emulator::init_seeded(0);
+    emulator.load_rom(&rom()).unwrap();
+    emulator.step_instruction();
+    assert_eq!(emulator.cpu.get_pc(), 0xE000);
+
+    let output = Output::default();
+    let tracer = Tracer::new(output.clone(), format).unwrap();
+    emulator.tracer = Some(tracer.with_options(&[Processor::Cpu], start, stop));
+    (emulator, output)
+}
+
+/// The line the text format should give for the cpu's next instruction.
+fn expected_line(emulator: &Emulator, cycles: i32, bytes_and_instruction: &str) -> String {
+    let cpu = &emulator.cpu;
+    format!("cpu {cycles:>10} {:04X}  {bytes_and_instruction} A:{:02X} X:{:02X} Y:{:02X} S:{:02X} P:{:02X} BK:{:02X}",
+        cpu.get_pc(), cpu.get_a(), cpu.get_x(), cpu.get_y(), cpu.get_s(), cpu.get_p(), emulator.cpu_bus.system_control.banking_register.0)
+}
+
+#[test]
+fn text_lines_show_the_state_before_each_instruction() {
+    let (mut emulator, output) = traced(TraceFormat::Text, None, None);
+    let lda = expected_line(&emulator, 0, "A9 12     LDA #$12        ");
+    let cycles = emulator.step_instruction();
+    let ldx = expected_line(&emulator, cycles, "A2 34     LDX #$34        ");
+    emulator.step_instruction();
+
+    // only the 2M cart has a bank to show
+    assert_eq!(output.text(), format!("{lda}\n{ldx}\n"));
+    assert!(ldx.contains(" A:12 "), "a should be as lda left it");
+}
+
+#[test]
+fn binary_records_match_the_documented_layout() {
+    let (mut emulator, output) = traced(TraceFormat::Binary, None, None);
+    let cpu = &emulator.cpu;
+    let registers = [cpu.get_a(), cpu.get_x(), cpu.get_y(), cpu.get_s(), cpu.get_p()];
+    let banking = emulator.cpu_bus.system_control.banking_register.0;
+    let cycles = emulator.step_instruction();
+    emulator.step_instruction();
+
+    let trace = output.0.borrow();
+    assert_eq!(trace[..5], *b"GTTR\x01");
+    let records: Vec<_> = trace[5..].chunks(16).collect();
+    assert_eq!(records.len(), 2);
+    assert!(records.iter().all(|r| r.len() == 16));
+
+    let mut first = vec![0, 0, 0, 0, 0x00, 0xE0, 0xA9, 0x12, 0x00];
+    first.extend(registers);
+    first.extend([banking, 0]);
+    assert_eq!(records[0], first);
+    assert_eq!(records[1][0..4], (cycles as u32).to_le_bytes());
+    assert_eq!(records[1][4..9], [0x02, 0xE0, 0xA2, 0x34, 0x00], "operands are padded with zeros, not the next instruction");
+    assert_eq!(records[1][9], 0x12, "a should be as lda left it");
+    assert_eq!(records[1][15] & 0x80, 0, "the acp bit is set on a cpu record");
+}
+
+#[test]
+fn address_triggers_start_and_stop_on_that_instruction() {
+    let start = TraceTrigger::Address(Processor::Cpu, 0xE004);
+    let stop = TraceTrigger::Address(Processor::Cpu, 0xE005);
+    let (mut emulator, output) = traced(TraceFormat::Text, Some(start), Some(stop));
+    for _ in 0..10 {
+        emulator.step_instruction();
+    }
+
+    let text = output.text();
+    let pcs: Vec<_> = text.lines().map(|line| &line[15..19]).collect();
+    assert_eq!(pcs, ["E004"], "only the instruction at the start trigger, and not the one at the stop trigger");
+    assert!(!emulator.tracer.as_ref().unwrap().is_tracing());
+
+    // a trigger for the other processor never fires
+    let acp_start = TraceTrigger::Address(Processor::Acp, 0xE000);
+    let (mut emulator, output) = traced(TraceFormat::Text, Some(acp_start), None);
+    emulator.step_instruction();
+    assert!(output.text().is_empty());
+}
+
+#[test]
+fn frame_triggers_trace_whole_frames() {
+    let (mut emulator, output) = traced(TraceFormat::Text, Some(TraceTrigger::Frame(1)), Some(TraceTrigger::Frame(2)));
+    emulator.run_frame();
+    assert!(output.text().is_empty(), "traced before the start frame");
+
+    emulator.run_frame();
+    let traced = output.text().lines().count();
+    assert!(traced > 1000, "only traced {traced} instructions of frame 1");
+    assert!(emulator.tracer.as_ref().unwrap().is_tracing());
+
+    emulator.run_frame();
+    assert_eq!(output.text().lines().count(), traced, "traced past the stop frame");
+    assert!(!emulator.tracer.as_ref().unwrap().is_tracing());
+}
@@ -0,0 +1,163 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A piece of memory to watch. Banked memory is watched per bank, so a watchpoint on
+/// `ram1:$0203` doesn't fire while the game is poking bank 0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WatchTarget {
+    /// whatever is mapped at this cpu address at the time
+    Cpu(u16),
+    /// system ram $0000-$1FFF, in one of the 4 banks
+    Ram(u8, u16),
+    /// audio ram $000-$FFF, from either processor
+    Aram(u16),
+    /// one of the 8 256x256 vram pages
+    Vram(u8, u16),
+    /// one of the 2 128x128 framebuffers
+    Framebuffer(u8, u16),
+}
+
+impl Display for WatchTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchTarget::Cpu(address) => write!(f, "${address:04X}"),
+            WatchTarget::Ram(bank, address) => write!(f, "ram{bank}:${address:04X}"),
+            WatchTarget::Aram(address) => write!(f, "aram:${address:03X}"),
+            WatchTarget::Vram(page, address) => write!(f, "vram{page}:${address:04X}"),
+            WatchTarget::Framebuffer(fb, address) => write!(f, "fb{fb}:${address:04X}"),
+        }
+    }
+}
+
+/// Parses the same syntax as `Display`, e.g. `$0203`, `ram1:0203`, `aram:040`, `vram3:$8000` or `fb0:1F00`.
+impl FromStr for WatchTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (space, address) = s.trim().split_once(':').unwrap_or(("", s.trim()));
+        let address = u16::from_str_radix(address.trim_start_matches('$'), 16)
+            .map_err(|_| format!("bad address in {s:?}"))?;
+
+        let (name, index) = space.split_at(space.find(|c: char| c.is_ascii_digit()).unwrap_or(space.len()));
+        let index: Option<u8> = index.parse().ok();
+
+        let target = match (name, index) {
+            ("", None) => WatchTarget::Cpu(address),
+            ("ram", Some(bank)) if bank < 4 && address < 0x2000 => WatchTarget::Ram(bank, address),
+            ("aram", None) if address < 0x1000 => WatchTarget::Aram(address),
+            ("vram", Some(page)) if page < 8 => WatchTarget::Vram(page, address),
+            ("fb", Some(fb)) if fb < 2 && address < 0x4000 => WatchTarget::Framebuffer(fb, address),
+            _ => return Err(format!("unknown watch target {s:?}")),
+        };
+        Ok(target)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: WatchKind) -> bool {
+        self == WatchKind::ReadWrite || self == access
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Watchpoint {
+    pub target: WatchTarget,
+    pub kind: WatchKind,
+    /// only fire when this value is read or written
+    pub value: Option<u8>,
+}
+
+impl Display for Watchpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            WatchKind::Read => "r",
+            WatchKind::Write => "w",
+            WatchKind::ReadWrite => "rw",
+        };
+        write!(f, "{kind} {}", self.target)?;
+        if let Some(value) = self.value {
+            write!(f, " == ${value:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A watched access, as seen from the processor that made it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub access: WatchKind,
+    /// the bus address that was accessed, or for the blitter, the address within the watched memory
+    pub address: u16,
+    pub data: u8,
+    /// the blitter made the access, partway through whatever instruction the cpu was running
+    pub blitter: bool,
+}
+
+impl Display for WatchHit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.blitter {
+            let verb = if self.access == WatchKind::Write { "wrote" } else { "read" };
+            return write!(f, "blitter {verb} ${:02X} ({})", self.data, self.watchpoint)
+        }
+        match self.access {
+            WatchKind::Write => write!(f, "wrote ${:02X} to ${:04X}", self.data, self.address)?,
+            _ => write!(f, "read ${:02X} from ${:04X}", self.data, self.address)?,
+        }
+        write!(f, " ({})", self.watchpoint)
+    }
+}
+
+/// The watchpoints a bus checks on every access. The emulator collects `hit` after each instruction.
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+    pub list: Vec<Watchpoint>,
+    /// the first hit during the current instruction
+    pub hit: Option<WatchHit>,
+}
+
+impl Watchpoints {
+    /// `location` is the memory actually behind `address` right now, if it's something watchable.
+    #[inline]
+    pub(crate) fn check(&mut self, address: u16, location: Option<WatchTarget>, access: WatchKind, data: u8) {
+        if self.list.is_empty() || self.hit.is_some() {
+            return
+        }
+
+        let hit = self.list.iter().find(|w| {
+            (w.target == WatchTarget::Cpu(address) || Some(w.target) == location)
+                && w.kind.matches(access)
+                && w.value.is_none_or(|v| v == data)
+        });
+        if let Some(watchpoint) = hit {
+            self.hit = Some(WatchHit { watchpoint: *watchpoint, access, address, data, blitter: false });
+        }
+    }
+
+    /// An access the blitter made to `target`. It doesn't go through the cpu's address space,
+    /// so only watchpoints on that exact vram or framebuffer byte see it.
+    #[inline]
+    pub(crate) fn check_blitter(&mut self, target: WatchTarget, access: WatchKind, data: u8) {
+        if self.list.is_empty() || self.hit.is_some() {
+            return
+        }
+
+        let hit = self.list.iter().find(|w| {
+            w.target == target && w.kind.matches(access) && w.value.is_none_or(|v| v == data)
+        });
+        if let Some(watchpoint) = hit {
+            let address = match target {
+                WatchTarget::Vram(_, address) | WatchTarget::Framebuffer(_, address) => address,
+                _ => 0,
+            };
+            self.hit = Some(WatchHit { watchpoint: *watchpoint, access, address, data, blitter: true });
+        }
+    }
+}
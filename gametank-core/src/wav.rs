@@ -0,0 +1,118 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use tracing::warn;
+
+/// Writes unsigned 8-bit mono PCM, which is exactly what the ACP's DAC gets fed.
+pub fn write_u8_mono<W: Write>(mut w: W, sample_rate: u32, samples: &[u8]) -> io::Result<()> {
+    write_header(&mut w, 1, sample_rate, 8, samples.len() as u32)?;
+    w.write_all(samples)
+}
+
+fn write_header<W: Write>(w: &mut W, format: u16, sample_rate: u32, bits_per_sample: u16, data_len: u32) -> io::Result<()> {
+    let block_align = bits_per_sample / 8;
+    w.write_all(b"RIFF")?;
+    w.write_all(&(36 + data_len).to_le_bytes())?;
+    w.write_all(b"WAVE")?;
+
+    w.write_all(b"fmt ")?;
+    w.write_all(&16u32.to_le_bytes())?;
+    w.write_all(&format.to_le_bytes())?;
+    w.write_all(&1u16.to_le_bytes())?; // mono
+    w.write_all(&sample_rate.to_le_bytes())?;
+    w.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    w.write_all(&block_align.to_le_bytes())?;
+    w.write_all(&bits_per_sample.to_le_bytes())?;
+
+    w.write_all(b"data")?;
+    w.write_all(&data_len.to_le_bytes())
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// unsigned 8-bit, like the DAC
+    U8,
+    /// signed 16-bit, for anything that's been through a resampler
+    I16,
+}
+
+impl SampleFormat {
+    fn bits(self) -> u16 {
+        match self {
+            SampleFormat::U8 => 8,
+            SampleFormat::I16 => 16,
+        }
+    }
+}
+
+/// Mono PCM written a sample at a time, for recordings that don't have an end in sight.
+/// The header's lengths are patched in by [`WavWriter::finish`], or on drop.
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    format: SampleFormat,
+    sample_rate: u32,
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create(path: &Path, sample_rate: u32, format: SampleFormat) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), sample_rate, format)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, format: SampleFormat) -> io::Result<Self> {
+        write_header(&mut out, 1, sample_rate, format.bits(), 0)?;
+        Ok(Self { out, format, sample_rate, data_len: 0, finished: false })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.data_len / (self.format.bits() / 8) as u32
+    }
+
+    pub fn write_u8(&mut self, sample: u8) -> io::Result<()> {
+        debug_assert_eq!(self.format, SampleFormat::U8);
+        self.out.write_all(&[sample])?;
+        self.data_len += 1;
+        self.finished = false;
+        Ok(())
+    }
+
+    pub fn write_i16(&mut self, sample: i16) -> io::Result<()> {
+        debug_assert_eq!(self.format, SampleFormat::I16);
+        self.out.write_all(&sample.to_le_bytes())?;
+        self.data_len += 2;
+        self.finished = false;
+        Ok(())
+    }
+
+    /// Takes -1.0..1.0, clipping anything outside it.
+    pub fn write_f32(&mut self, sample: f32) -> io::Result<()> {
+        self.write_i16((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)
+    }
+
+    /// Fills in the header's lengths. More samples can still be written afterwards, they'll just need another `finish`.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.out.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.out, 1, self.sample_rate, self.format.bits(), self.data_len)?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.finish() {
+                warn!("couldn't finish wav file: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+mod script;
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::rc::Rc;
+use clap::Parser;
+use tracing::{info, warn, Level};
+use gametank_core::color_map::COLOR_MAP;
+use gametank_core::emulator::{HEIGHT, WIDTH};
+use gametank_core::{AudioOutput, Emulator};
+use gametank_core::trace::TraceArgs;
+
+/// Runs a GameTank rom with no window or GPU, for regression testing in CI.
+///
+/// Exits with 1 when the run doesn't match the golden file, and 2 on any other error.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+struct Args {
+    /// .gtr rom to run
+    rom: PathBuf,
+
+    /// how many frames to run for
+    #[arg(long, default_value_t = 600)]
+    frames: u64,
+
+    /// input script, one `<frame> press|release <input>` per line
+    #[arg(long)]
+    input: Option<PathBuf>,
+
+    /// seed for the power-on garbage in the framebuffers
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// finish blits the moment they start
+    #[arg(long)]
+    instant_blit: bool,
+
+    /// save the final screen as a png
+    #[arg(long)]
+    png: Option<PathBuf>,
+
+    /// save everything the ACP played as an 8-bit wav
+    #[arg(long)]
+    wav: Option<PathBuf>,
+
+    /// compare the run's hashes against this file
+    #[arg(long)]
+    golden: Option<PathBuf>,
+
+    /// write the golden file instead of comparing against it
+    #[arg(long, requires = "golden")]
+    bless: bool,
+
+    /// one of error, warn, info, debug, trace
+    #[arg(long, default_value_t = Level::ERROR)]
+    log_level: Level,
+
+    #[command(flatten)]
+    trace: TraceArgs,
+}
+
+/// Collects every sample the ACP outputs, at whatever rate it was running at first.
+#[derive(Default)]
+struct CapturedAudio {
+    samples: Vec<u8>,
+    sample_rate: Option<f64>,
+}
+
+struct CaptureOutput(Rc<RefCell<CapturedAudio>>);
+
+impl AudioOutput for CaptureOutput {
+    fn push_sample(&mut self, sample: u8, sample_rate: f64) {
+        let mut audio = self.0.borrow_mut();
+        if *audio.sample_rate.get_or_insert(sample_rate) != sample_rate {
+            warn!("sample rate changed to {sample_rate:.0}hz mid-run, the wav will play at the wrong speed");
+            audio.sample_rate = Some(sample_rate);
+        }
+        audio.samples.push(sample);
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// FNV-1a, because it's stable across rust versions and platforms, unlike `DefaultHasher`
+fn fnv1a<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in chunks.into_iter().flatten() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn run(args: &Args) -> Result<bool, String> {
+    let rom = std::fs::read(&args.rom).map_err(|e| format!("couldn't read {}: {e}", args.rom.display()))?;
+    let events = match &args.input {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+            script::parse(&text).map_err(|e| format!("{}: {e}", path.display()))?
+        }
+        None => Vec::new(),
+    };
+
+    let mut emulator = Emulator::init_seeded(args.seed);
+    // load from memory so no .sav gets read or written next to the rom
+    emulator.load_rom(&rom).map_err(|e| format!("couldn't load {}: {e}", args.rom.display()))?;
+    emulator.instant_blit = args.instant_blit;
+    emulator.tracer = args.trace.tracer()?;
+
+    let audio = Rc::new(RefCell::new(CapturedAudio::default()));
+    emulator.audio_out = Some(Box::new(CaptureOutput(audio.clone())));
+
+    let mut events = events.into_iter().peekable();
+    for frame in 0..args.frames {
+        while let Some(event) = events.next_if(|e| e.frame <= frame) {
+            emulator.set_input_state(event.command, event.pressed);
+        }
+        emulator.run_frame();
+    }
+    info!("ran {} frames", args.frames);
+    if let (Some(tracer), Some(path)) = (&mut emulator.tracer, &args.trace.trace) {
+        tracer.flush().map_err(|e| format!("couldn't write {}: {e}", path.display()))?;
+    }
+
+    let framebuffer = emulator.cpu_bus.read_full_framebuffer().clone();
+    let audio = audio.borrow();
+
+    let mut results = BTreeMap::new();
+    results.insert("framebuffer", fnv1a([&framebuffer[..]]));
+    results.insert("ram", fnv1a(emulator.cpu_bus.ram_banks.iter().map(|b| &b[..])));
+    results.insert("vram", fnv1a(emulator.cpu_bus.vram_banks.iter().map(|b| &b[..])));
+    results.insert("audio", fnv1a([&audio.samples[..]]));
+    for (name, hash) in &results {
+        println!("{name} {hash:016x}");
+    }
+
+    if let Some(path) = &args.png {
+        let pixels: Vec<u8> = framebuffer.iter().flat_map(|i| {
+            let (r, g, b, a) = COLOR_MAP[*i as usize];
+            [r, g, b, a]
+        }).collect();
+        image::save_buffer(path, &pixels, WIDTH, HEIGHT, image::ExtendedColorType::Rgba8)
+            .map_err(|e| format!("couldn't save {}: {e}", path.display()))?;
+    }
+
+    if let Some(path) = &args.wav {
+        let sample_rate = audio.sample_rate.unwrap_or(0.0).round() as u32;
+        File::create(path)
+            .and_then(|f| gametank_core::wav::write_u8_mono(BufWriter::new(f), sample_rate, &audio.samples))
+            .map_err(|e| format!("couldn't save {}: {e}", path.display()))?;
+    }
+
+    let Some(golden) = &args.golden else {
+        return Ok(true)
+    };
+
+    let golden_text: String = std::iter::once(format!("frames {}", args.frames))
+        .chain(results.iter().map(|(name, hash)| format!("{name} {hash:016x}")))
+        .map(|line| line + "\n")
+        .collect();
+
+    if args.bless {
+        std::fs::write(golden, golden_text).map_err(|e| format!("couldn't write {}: {e}", golden.display()))?;
+        info!("blessed {}", golden.display());
+        return Ok(true)
+    }
+
+    let expected = std::fs::read_to_string(golden).map_err(|e| format!("couldn't read {}: {e}", golden.display()))?;
+    let expected: BTreeMap<&str, &str> = expected.lines().filter_map(|l| l.split_once(' ')).collect();
+    let mut matches = true;
+    for line in golden_text.lines() {
+        let (name, actual) = line.split_once(' ').unwrap_or_default();
+        match expected.get(name) {
+            Some(e) if *e == actual => {}
+            Some(e) => {
+                eprintln!("{name} mismatch: expected {e}, got {actual}");
+                matches = false;
+            }
+            None => {
+                eprintln!("{name} missing from {}", golden.display());
+                matches = false;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(std::io::stderr)
+        .compact()
+        .init();
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::from(2)
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use gametank_core::input::ControllerButton::{Down, Left, Right, Start, Up, A, B, C};
+use gametank_core::input::{ControllerButton, InputCommand};
+
+/// One line of an input script: on `frame`, press or release `command`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub frame: u64,
+    pub command: InputCommand,
+    pub pressed: bool,
+}
+
+/// Parses an input script. Each line is `<frame> press|release <input>`, where input is one of
+/// `p1.<button>`, `p2.<button>`, `reset` or `hard-reset`, and buttons are up/down/left/right/a/b/c/start.
+/// Blank lines and anything after a `#` are ignored. Events are returned sorted by frame.
+pub fn parse(script: &str) -> Result<Vec<ScriptEvent>, String> {
+    let mut events = Vec::new();
+    for (n, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue
+        }
+
+        let fail = |why: &str| format!("line {}: {why}: {line:?}", n + 1);
+        let [frame, action, input] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+            return Err(fail("expected `<frame> press|release <input>`"))
+        };
+
+        let frame = frame.parse().map_err(|_| fail("bad frame number"))?;
+        let pressed = match action {
+            "press" => true,
+            "release" => false,
+            _ => return Err(fail("expected press or release")),
+        };
+        let command = parse_input(input).ok_or_else(|| fail("unknown input"))?;
+
+        events.push(ScriptEvent { frame, command, pressed });
+    }
+
+    events.sort_by_key(|e| e.frame);
+    Ok(events)
+}
+
+fn parse_input(input: &str) -> Option<InputCommand> {
+    match input {
+        "reset" => return Some(InputCommand::SoftReset),
+        "hard-reset" => return Some(InputCommand::HardReset),
+        _ => {}
+    }
+
+    let (pad, button) = input.split_once('.')?;
+    let button = parse_button(button)?;
+    match pad {
+        "p1" => Some(InputCommand::Controller1(button)),
+        "p2" => Some(InputCommand::Controller2(button)),
+        _ => None,
+    }
+}
+
+fn parse_button(button: &str) -> Option<ControllerButton> {
+    Some(match button {
+        "up" => Up,
+        "down" => Down,
+        "left" => Left,
+        "right" => Right,
+        "a" => A,
+        "b" => B,
+        "c" => C,
+        "start" => Start,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,55 @@
+//! Parsing input scripts, and the errors a bad one gets.
+
+use gametank_core::input::ControllerButton::{Start, A};
+use gametank_core::input::InputCommand::{self, Controller1, Controller2, HardReset, SoftReset};
+use crate::script::{parse, ScriptEvent};
+
+fn event(frame: u64, command: InputCommand, pressed: bool) -> ScriptEvent {
+    ScriptEvent { frame, command, pressed }
+}
+
+#[test]
+fn events_come_out_in_frame_order() {
+    let script = "\
+        # start the game, then hold a
+        60 press p1.start
+        120 release p1.a   # let go of a later on
+        30 press reset
+
+        60 release p1.start
+        90 press p1.a
+        200 press p2.a
+        0 press hard-reset
+    ";
+    assert_eq!(parse(script).unwrap(), [
+        event(0, HardReset, true),
+        event(30, SoftReset, true),
+        event(60, Controller1(Start), true),
+        event(60, Controller1(Start), false),
+        event(90, Controller1(A), true),
+        event(120, Controller1(A), false),
+        event(200, Controller2(A), true),
+    ]);
+}
+
+#[test]
+fn same_frame_events_keep_script_order() {
+    // a tap within one frame has to stay a press then a release, or the game never sees it
+    let events = parse("5 release p1.a\n5 press p1.a\n3 press p1.b\n5 release p1.a\n").unwrap();
+    let frame_5: Vec<_> = events.iter().filter(|e| e.frame == 5).map(|e| e.pressed).collect();
+    assert_eq!(frame_5, [false, true, false]);
+    assert_eq!(events[0].frame, 3);
+}
+
+#[test]
+fn bad_lines_are_reported_with_their_line_number() {
+    let error = |script: &str| parse(script).unwrap_err();
+
+    assert_eq!(error("1 press p1.a\n\n2 press p3.a\n"), "line 3: unknown input: \"2 press p3.a\"");
+    assert_eq!(error("1 press p1.x"), "line 1: unknown input: \"1 press p1.x\"");
+    assert_eq!(error("1 press select"), "line 1: unknown input: \"1 press select\"");
+    assert_eq!(error("# header\n1 hold p1.a"), "line 2: expected press or release: \"1 hold p1.a\"");
+    assert_eq!(error("-1 press p1.a"), "line 1: bad frame number: \"-1 press p1.a\"");
+    assert_eq!(error("1 press"), "line 1: expected `<frame> press|release <input>`: \"1 press\"");
+    assert_eq!(error("1 press p1.a p1.b # two at once"), "line 1: expected `<frame> press|release <input>`: \"1 press p1.a p1.b\"");
+}
@@ -5,6 +5,7 @@ use winit::window::WindowId;
 use crate::app_initialized::AppInitialized;
 use crate::app_uninit::App;
 
+#[allow(clippy::large_enum_variant)]
 pub enum DelegatedApp {
     Uninitialized(App),
     Initialized(AppInitialized),
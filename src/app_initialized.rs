@@ -1,6 +1,5 @@
 use std::cell::{Cell, OnceCell};
-use std::fs::File;
-use std::io::Read;
+use std::collections::HashMap;
 use std::sync::Arc;
 use egui::{epaint, vec2, Align, Button, Color32, Frame, Id, LayerId, Layout, Pos2, Rect, ResizeDirection, ScrollArea, TextureOptions, Ui, UiBuilder, Vec2, ViewportCommand};
 use egui_wgpu::ScreenDescriptor;
@@ -9,25 +8,40 @@ use winit::application::ApplicationHandler;
 use winit::dpi::LogicalSize;
 use winit::event::{KeyEvent, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
+use winit::keyboard::Key;
 use winit::window::{Window, WindowId};
+use gametank_core::gdb::GdbServer;
+use gametank_core::input::InputCommand;
+use gametank_core::rewind::DEFAULT_REWIND_BUDGET;
 use crate::app_ui::gametankboy::GameTankBoyUI;
 use crate::app_ui::ram_inspector::MemoryInspector;
+use crate::app_ui::debugger::DebuggerPanel;
+use crate::app_ui::disassembly::DisassemblyPanel;
 use crate::app_ui::vram_viewer::{VRAMViewer, VRAMViewerLayout};
 use crate::app_uninit::App;
-use crate::emulator::color_map::{COLOR_MAP, COLOR_MAP_PERCEPTUALLY_AUTOMAPPED, COLOR_MAP_WRONG};
+use crate::audio_output::cpal_output;
+use crate::palette::Palette;
 use crate::egui_renderer::EguiRenderer;
-use crate::emulator::emulator::{Emulator, HEIGHT, WIDTH};
+use gametank_core::emulator::{Emulator, HEIGHT, WIDTH};
 use crate::graphics::GraphicsContext;
+use crate::resampler::Resampler;
 
 pub struct AppInitialized {
     pub emulator: Emulator,
     pub gc: GraphicsContext,
     pub window: Arc<Window>,
     pub egui_renderer: EguiRenderer,
+    pub input_bindings: HashMap<Key, InputCommand>,
 
     pub console_gui: GameTankBoyUI,
     pub vram_viewer: VRAMViewer,
     pub mem_inspector: MemoryInspector,
+    pub debugger_panel: DebuggerPanel,
+    pub disassembly: DisassemblyPanel,
+
+    /// shown in a popup until dismissed
+    pub rom_error: Option<String>,
+    pub gdb: Option<GdbServer>,
 
     show_left_pane: bool,
     show_right_pane: bool,
@@ -40,9 +54,11 @@ impl From<&mut App> for AppInitialized {
         let mut gc = app.gc.take().unwrap();
         let window = app.window.take().unwrap();
         let egui_renderer = app.egui_renderer.take().unwrap();
-        let console_gui = GameTankBoyUI::init(egui_renderer.context(), Self::buffer_to_color_image(&emulator.cpu_bus.read_full_framebuffer()));
+        let mut console_gui = GameTankBoyUI::init(egui_renderer.context(), Self::buffer_to_color_image(&emulator.cpu_bus.read_full_framebuffer()));
         let vram_viewer = VRAMViewer::new(VRAMViewerLayout::Pages, egui_renderer.context(), &mut emulator);
 
+        console_gui.desired_scale = Some(app.scale);
+
         gc.surface_config.width = window.inner_size().width;
         gc.surface_config.height = window.inner_size().height;
         gc.surface.configure(&gc.device, &gc.surface_config);
@@ -52,9 +68,14 @@ impl From<&mut App> for AppInitialized {
             gc,
             window,
             egui_renderer,
+            input_bindings: crate::input::default_bindings(),
             console_gui,
             vram_viewer,
             mem_inspector: MemoryInspector {},
+            debugger_panel: DebuggerPanel::default(),
+            disassembly: DisassemblyPanel::default(),
+            rom_error: None,
+            gdb: app.gdb.take(),
             show_left_pane: true,
             show_right_pane: true,
             show_bottom_pane: true,
@@ -107,6 +128,59 @@ impl AppInitialized {
                     ui.toggle_value(&mut self.show_left_pane, "show left panel");
                     ui.toggle_value(&mut self.show_bottom_pane, "show bottom panel");
                     ui.toggle_value(&mut self.show_right_pane, "show right panel");
+
+                    let mut rewind = self.emulator.rewind.enabled();
+                    if ui.toggle_value(&mut rewind, "rewind")
+                        .on_hover_text("keep the last minute or so of play, to rewind through")
+                        .changed()
+                    {
+                        self.emulator.rewind.set_budget(if rewind { DEFAULT_REWIND_BUDGET } else { 0 });
+                    }
+
+                    ui.toggle_value(&mut self.emulator.audio_pacing, "audio pacing")
+                        .on_hover_text("run as fast as the sound card plays, instead of by the clock");
+
+                    let mut recording = self.emulator.is_capturing_audio();
+                    if ui.toggle_value(&mut recording, "record audio")
+                        .on_hover_text("save the raw acp output and the 48khz stream as wavs next to the rom")
+                        .changed()
+                    {
+                        if recording {
+                            start_audio_capture(&mut self.emulator);
+                        } else {
+                            self.emulator.stop_audio_capture();
+                        }
+                    }
+
+                    if let Some(audio) = cpal_output(&mut self.emulator.audio_out) {
+                        let current = audio.resampler();
+                        let taps = current.taps().unwrap_or(Resampler::DEFAULT_TAPS);
+                        let mut selected = current;
+                        ui.label("resampler:");
+                        ui.selectable_value(&mut selected, Resampler::Linear, "linear")
+                            .on_hover_text("cheapest, but square waves alias");
+                        ui.selectable_value(&mut selected, Resampler::Sinc { taps }, "sinc")
+                            .on_hover_text("smooth, band-limited to the acp's rate");
+                        ui.selectable_value(&mut selected, Resampler::BandLimitedStep { taps }, "blep")
+                            .on_hover_text("the dac's hard edges, band-limited to 48khz");
+                        if selected != current {
+                            audio.set_resampler(selected);
+                        }
+
+                        let mut settings = audio.dac_filter();
+                        let mut enabled = !settings.bypass;
+                        ui.toggle_value(&mut enabled, "dac filter")
+                            .on_hover_text("model the console's analog output: dc blocking, an rc low-pass and slew limiting");
+                        settings.bypass = !enabled;
+                        if enabled {
+                            ui.add(egui::DragValue::new(&mut settings.high_pass_hz).range(0.0..=1000.0).suffix("hz hp"));
+                            ui.add(egui::DragValue::new(&mut settings.low_pass_hz).range(0.0..=24000.0).speed(10.0).suffix("hz lp"));
+                            ui.add(egui::DragValue::new(&mut settings.slew_per_ms).range(0.0..=200.0).speed(0.1).suffix("/ms slew"));
+                        }
+                        if settings != audio.dac_filter() {
+                            audio.set_dac_filter(settings);
+                        }
+                    }
                 });
             });
 
@@ -139,9 +213,11 @@ impl AppInitialized {
                         ui.with_layout(Layout::top_down_justified(Align::RIGHT), |ui| {
                             Frame::default().show(ui, |ui| {
                                 ui.set_min_width(24.0);
+                                self.debugger_panel.draw(ui, &mut self.emulator);
+                                ui.separator();
                                 // ui.set_width(ui.available_width());
                                 ui.set_height(ui.available_height());
-                                ui.label("here's some gui shit");
+                                self.disassembly.draw(ui, &mut self.emulator, &mut self.debugger_panel);
                             })
                         });
 
@@ -151,6 +227,17 @@ impl AppInitialized {
             });
         }
 
+        if let Some(message) = &self.rom_error {
+            let mut dismissed = false;
+            egui::Window::new("couldn't load rom").collapsible(false).resizable(false).show(self.egui_renderer.context(), |ui| {
+                ui.label(message);
+                dismissed = ui.button("ok").clicked();
+            });
+            if dismissed {
+                self.rom_error = None;
+            }
+        }
+
         egui::CentralPanel::default().frame(frame).show(self.egui_renderer.context(), |ui| {
             // Set the minimum size for the center pane
             let center_min_size = egui::vec2(128.0, 128.0);
@@ -176,16 +263,7 @@ impl AppInitialized {
 
 
     pub fn buffer_to_color_image(framebuffer: &[u8; 128*128]) -> egui::ColorImage {
-        let mut pixels: Vec<u8> = Vec::with_capacity(128 * 128 * 4); // 4 channels per pixel (RGBA)
-
-        for &index in framebuffer.iter() {
-            let (r, g, b, a) = COLOR_MAP[index as usize];
-            pixels.push(r);
-            pixels.push(g);
-            pixels.push(b);
-            pixels.push(a);
-        }
-
+        let pixels = Palette::selected().to_rgba(framebuffer);
         egui::ColorImage::from_rgba_unmultiplied([128, 128], &pixels)
     }
 
@@ -197,14 +275,32 @@ impl AppInitialized {
     }
 }
 
+/// Records to `<rom>-raw.wav` and `<rom>-48k.wav`, or `gametank-*.wav` for the built-in game.
+fn start_audio_capture(emulator: &mut Emulator) {
+    let base = emulator.save_path.as_ref()
+        .map_or_else(|| "gametank".into(), |path| path.with_extension(""));
+    let with_suffix = |suffix: &str| {
+        let mut path = base.clone().into_os_string();
+        path.push(suffix);
+        std::path::PathBuf::from(path)
+    };
+    let raw = with_suffix("-raw.wav");
+    let output = emulator.audio_out.is_some().then(|| with_suffix("-48k.wav"));
+
+    match emulator.start_audio_capture(Some(&raw), output.as_deref()) {
+        Ok(()) => warn!("recording audio to {}", raw.display()),
+        Err(e) => error!("couldn't start audio capture: {e}"),
+    }
+}
+
 use std::cell::RefCell;
 use wasm_bindgen::prelude::*;
 use crate::PlayState::{Paused, Playing, WasmInit};
 
 // Use `thread_local!` to store per-thread global data in WASM
 thread_local! {
-    static ROM_DATA: RefCell<Option<Vec<u8>>> = RefCell::new(None);
-    static SHOULD_SHUTDOWN: Cell<bool> = Cell::new(false);
+    static ROM_DATA: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    static SHOULD_SHUTDOWN: Cell<bool> = const { Cell::new(false) };
 }
 
 // Function to update the ROM data from JavaScript
@@ -227,6 +323,10 @@ impl ApplicationHandler for AppInitialized {
         todo!()
     }
 
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        self.emulator.flush_cartridge_save();
+    }
+
     fn window_event(&mut self, event_loop: &ActiveEventLoop, window_id: WindowId, event: WindowEvent) {
         self.emulator.process_cycles(false);
         self.egui_renderer.handle_input(&self.window, &event);
@@ -246,25 +346,39 @@ impl ApplicationHandler for AppInitialized {
             }
             WindowEvent::KeyboardInput { event, .. } => {
                 let KeyEvent {  logical_key,   state,  .. } = event;
-                self.emulator.set_input_state(logical_key, state);
+                if let Some(command) = self.input_bindings.get(&logical_key) {
+                    self.emulator.set_input_state(*command, state.is_pressed());
+                }
             },
             WindowEvent::MouseInput { .. } => { self.emulator.wasm_init(); }
             WindowEvent::Touch(_) => { self.emulator.wasm_init(); }
             WindowEvent::DroppedFile(path) => {
                 warn!("reading file from path...");
                 // check if filename ends in .gtr and load file into slice
-                let filename = path.file_name().unwrap().to_str().unwrap();
+                let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+                if filename.ends_with(".dbg") || filename.ends_with(".lbl") {
+                    if let Err(e) = self.emulator.load_symbols(&path) {
+                        error!("couldn't load symbols from {}: {e}", filename);
+                        self.rom_error = Some(format!("{filename}: {e}"));
+                    }
+                    return
+                }
                 if !filename.ends_with(".gtr") {
                     error!("not a valid gtr");
+                    self.rom_error = Some(format!("{filename} isn't a .gtr file"));
                     return
                 }
 
-                let mut file = File::open(&path).unwrap();
-                let mut bytes = Vec::new();
-                file.read_to_end(&mut bytes).unwrap();
-
-                self.emulator.load_rom(bytes.as_slice());
-                warn!("successfully loaded {}", filename);
+                match self.emulator.load_rom_file(&path) {
+                    Ok(()) => {
+                        warn!("successfully loaded {}", filename);
+                        self.rom_error = None;
+                    }
+                    Err(e) => {
+                        error!("couldn't load {}: {e}", filename);
+                        self.rom_error = Some(format!("{filename}: {e}"));
+                    }
+                }
             }
             _ => (),
         }
@@ -275,7 +389,10 @@ impl ApplicationHandler for AppInitialized {
         if let Some(data) = &ROM_DATA.take() {
             warn!("got rom data!");
             if !data.is_empty() {
-                self.emulator.load_rom(data);
+                if let Err(e) = self.emulator.load_rom(data) {
+                    error!("couldn't load rom: {e}");
+                    self.rom_error = Some(e.to_string());
+                }
             }
         }
 
@@ -283,6 +400,9 @@ impl ApplicationHandler for AppInitialized {
             event_loop.exit();
         }
 
+        if let Some(gdb) = &mut self.gdb {
+            gdb.poll(&mut self.emulator);
+        }
         self.emulator.process_cycles(false);
     }
 }
\ No newline at end of file
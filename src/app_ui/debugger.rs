@@ -0,0 +1,178 @@
+use egui::{Color32, Grid, RichText, Ui};
+use gametank_core::debugger::{Breakpoint, Processor};
+use gametank_core::emulator::Emulator;
+use gametank_core::watchpoint::{WatchKind, WatchTarget, Watchpoint};
+use gametank_core::PlayState;
+
+pub struct DebuggerPanel {
+    pub processor: Processor,
+    /// address for "run to cursor", as typed
+    pub cursor: String,
+    new_breakpoint: String,
+    new_watch_target: String,
+    new_watch_kind: WatchKind,
+    new_watch_value: String,
+}
+
+impl Default for DebuggerPanel {
+    fn default() -> Self {
+        Self {
+            processor: Processor::Cpu,
+            cursor: String::new(),
+            new_breakpoint: String::new(),
+            new_watch_target: String::new(),
+            new_watch_kind: WatchKind::Write,
+            new_watch_value: String::new(),
+        }
+    }
+}
+
+fn parse_address(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim().trim_start_matches('$'), 16).ok()
+}
+
+/// An address, or a cpu symbol name (with its bank, if it's banked).
+fn parse_breakpoint(emulator: &Emulator, processor: Processor, text: &str) -> Option<Breakpoint> {
+    match parse_address(text) {
+        Some(address) => Some(Breakpoint::new(processor, address)),
+        None if processor == Processor::Cpu => emulator.symbol_breakpoint(text.trim()),
+        None => None,
+    }
+}
+
+impl DebuggerPanel {
+    pub fn draw(&mut self, ui: &mut Ui, emulator: &mut Emulator) {
+        ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.processor, Processor::Cpu, "cpu");
+            ui.selectable_value(&mut self.processor, Processor::Acp, "acp");
+        });
+
+        let status = match (&emulator.debugger.halted, emulator.play_state) {
+            (Some(reason), _) => RichText::new(reason.to_string()).color(Color32::YELLOW),
+            (None, PlayState::Playing) => RichText::new("running").color(Color32::GREEN),
+            (None, _) => RichText::new("paused").color(Color32::GRAY),
+        };
+        ui.label(status);
+
+        let pc = emulator.pc(self.processor);
+        if let Some(symbol) = emulator.symbol_at(self.processor, pc) {
+            ui.label(format!("{symbol}:"));
+        }
+        if let Some((file, line)) = emulator.source_line(self.processor, pc) {
+            ui.label(RichText::new(format!("{file}:{line}")).color(Color32::LIGHT_BLUE));
+        }
+
+        let cpu = match self.processor {
+            Processor::Cpu => &emulator.cpu,
+            Processor::Acp => &emulator.acp,
+        };
+        Grid::new("registers").num_columns(2).show(ui, |ui| {
+            ui.label("PC"); ui.label(format!("{:04X}", cpu.get_pc())); ui.end_row();
+            ui.label("A"); ui.label(format!("{:02X}", cpu.get_a())); ui.end_row();
+            ui.label("X"); ui.label(format!("{:02X}", cpu.get_x())); ui.end_row();
+            ui.label("Y"); ui.label(format!("{:02X}", cpu.get_y())); ui.end_row();
+            ui.label("S"); ui.label(format!("{:02X}", cpu.get_s())); ui.end_row();
+
+            let p = cpu.get_p();
+            let flags: String = "NV1BDIZC".chars().enumerate()
+                .map(|(i, c)| if p & (0x80 >> i) != 0 { c } else { '-' })
+                .collect();
+            ui.label("P"); ui.label(format!("{:02X} {}", p, flags)); ui.end_row();
+        });
+
+        ui.horizontal(|ui| {
+            if emulator.play_state == PlayState::Playing {
+                if ui.button("pause").clicked() {
+                    emulator.play_state = PlayState::Paused;
+                }
+            } else if ui.button("continue").clicked() {
+                emulator.resume();
+            }
+            if ui.button("step").clicked() {
+                emulator.step_into(self.processor);
+            }
+            if ui.button("step over").clicked() {
+                emulator.step_over(self.processor);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.cursor).desired_width(80.0).hint_text("addr/symbol"));
+            let cursor = parse_breakpoint(emulator, self.processor, &self.cursor);
+            if ui.add_enabled(cursor.is_some(), egui::Button::new("run to cursor")).clicked() {
+                emulator.run_to_cursor(self.processor, cursor.map(|c| c.address).unwrap_or_default());
+            }
+        });
+
+        ui.checkbox(&mut emulator.debugger.pause_on_brk, "pause on BRK");
+        ui.checkbox(&mut emulator.instant_blit, "instant blits");
+
+        ui.separator();
+        ui.label("breakpoints");
+        let mut remove = None;
+        for breakpoint in &emulator.debugger.breakpoints {
+            ui.horizontal(|ui| {
+                let label = match emulator.debugger.symbols.label(breakpoint.address, breakpoint.bank) {
+                    Some(symbol) if breakpoint.processor == Processor::Cpu => format!("{breakpoint} {symbol}"),
+                    _ => breakpoint.to_string(),
+                };
+                ui.label(label);
+                if ui.small_button("x").clicked() {
+                    remove = Some(*breakpoint);
+                }
+            });
+        }
+        if let Some(breakpoint) = remove {
+            emulator.debugger.breakpoints.remove(&breakpoint);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_breakpoint).desired_width(80.0).hint_text("addr/symbol"));
+            let breakpoint = parse_breakpoint(emulator, self.processor, &self.new_breakpoint);
+            if ui.add_enabled(breakpoint.is_some(), egui::Button::new("add")).clicked() {
+                emulator.debugger.breakpoints.extend(breakpoint);
+                self.new_breakpoint.clear();
+            }
+        });
+
+        ui.separator();
+        ui.label("watchpoints");
+        let mut remove = None;
+        for watchpoint in emulator.watchpoints() {
+            ui.horizontal(|ui| {
+                ui.label(watchpoint.to_string());
+                if ui.small_button("x").clicked() {
+                    remove = Some(*watchpoint);
+                }
+            });
+        }
+        if let Some(watchpoint) = remove {
+            emulator.remove_watchpoint(&watchpoint);
+        }
+
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_watch_target).desired_width(80.0).hint_text("ram1:0203"))
+                .on_hover_text("$addr, ram0-3:addr, aram:addr, vram0-7:addr or fb0-1:addr");
+            ui.selectable_value(&mut self.new_watch_kind, WatchKind::Read, "r");
+            ui.selectable_value(&mut self.new_watch_kind, WatchKind::Write, "w");
+            ui.selectable_value(&mut self.new_watch_kind, WatchKind::ReadWrite, "rw");
+        });
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_watch_value).desired_width(32.0).hint_text("=val"));
+            let target = self.new_watch_target.parse::<WatchTarget>().ok();
+            let value = self.new_watch_value.trim().trim_start_matches('$');
+            let value = if value.is_empty() { Some(None) } else { u8::from_str_radix(value, 16).ok().map(Some) };
+            if let (Some(target), Some(value)) = (target, value) {
+                if ui.button("watch").clicked() {
+                    emulator.add_watchpoint(Watchpoint { target, kind: self.new_watch_kind, value });
+                    self.new_watch_target.clear();
+                    self.new_watch_value.clear();
+                }
+            } else {
+                ui.add_enabled(false, egui::Button::new("watch"));
+            }
+        });
+    }
+}
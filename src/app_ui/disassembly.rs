@@ -0,0 +1,92 @@
+use egui::{Color32, RichText, ScrollArea, SelectableLabel, Ui};
+use gametank_core::debugger::{Breakpoint, Processor};
+use gametank_core::disassembler::{disassemble_range, find_start};
+use gametank_core::emulator::Emulator;
+use crate::app_ui::debugger::DebuggerPanel;
+
+const LINES: usize = 64;
+/// how far before the pc to start decoding, so there's some context above it
+const LOOKBACK: u16 = 32;
+
+pub struct DisassemblyPanel {
+    pub follow_pc: bool,
+    anchor: Option<(Processor, u16)>,
+    last_pc: Option<(Processor, u16)>,
+}
+
+impl Default for DisassemblyPanel {
+    fn default() -> Self {
+        Self {
+            follow_pc: true,
+            anchor: None,
+            last_pc: None,
+        }
+    }
+}
+
+impl DisassemblyPanel {
+    pub fn draw(&mut self, ui: &mut Ui, emulator: &mut Emulator, debugger: &mut DebuggerPanel) {
+        ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+
+        let processor = debugger.processor;
+        let pc = emulator.pc(processor);
+        let peek = |address| emulator.peek(processor, address);
+
+        ui.checkbox(&mut self.follow_pc, "follow pc");
+
+        let mut lines = match self.anchor {
+            Some((p, anchor)) if p == processor => disassemble_range(anchor, LINES, peek),
+            _ => vec![],
+        };
+        // re-anchor once the pc wanders off the bottom (or anywhere else we haven't decoded)
+        let visible = lines.iter().take(LINES - LOOKBACK as usize / 2).any(|i| i.address == pc);
+        if lines.is_empty() || (self.follow_pc && !visible) {
+            let anchor = find_start(pc, LOOKBACK, peek);
+            self.anchor = Some((processor, anchor));
+            lines = disassemble_range(anchor, LINES, peek);
+        }
+
+        let pc_moved = self.last_pc != Some((processor, pc));
+        self.last_pc = Some((processor, pc));
+
+        let mut toggle = None;
+        ScrollArea::vertical().id_salt("disassembly").max_height(320.0).show(ui, |ui| {
+            for instruction in &lines {
+                let address = instruction.address;
+                let bytes: Vec<String> = instruction.bytes[..instruction.size() as usize].iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect();
+
+                if let Some(symbol) = emulator.symbol_at(processor, address) {
+                    ui.label(RichText::new(format!("{symbol}:")).color(Color32::LIGHT_BLUE));
+                }
+
+                ui.horizontal(|ui| {
+                    let breakpoint = emulator.debugger.breakpoints.iter().any(|b| b.processor == processor && b.address == address);
+                    let marker = if breakpoint {
+                        RichText::new("●").color(Color32::RED)
+                    } else {
+                        RichText::new("○").color(Color32::from_gray(64))
+                    };
+                    if ui.small_button(marker).on_hover_text("toggle breakpoint").clicked() {
+                        toggle = Some(address);
+                    }
+
+                    let operand = instruction.operand_with(|a| emulator.symbol_at(processor, a).map(str::to_string));
+                    let text = format!("{address:04X}  {:<8}  {} {operand}", bytes.join(" "), instruction.mnemonic);
+                    let line = ui.add(SelectableLabel::new(address == pc, text));
+                    if line.clicked() {
+                        debugger.cursor = format!("{address:04X}");
+                    }
+                    if address == pc && pc_moved && self.follow_pc {
+                        line.scroll_to_me(Some(egui::Align::Center));
+                    }
+                });
+            }
+        });
+
+        if let Some(address) = toggle {
+            emulator.debugger.toggle_breakpoint(Breakpoint::new(processor, address));
+        }
+    }
+}
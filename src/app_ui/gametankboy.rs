@@ -5,7 +5,7 @@ use egui::load::{SizedTexture, TextureLoadResult, TexturePoll};
 use image::{GenericImageView, ImageFormat};
 use tracing::warn;
 use crate::egui_renderer::EguiRenderer;
-use crate::emulator::emulator::Emulator;
+use gametank_core::emulator::Emulator;
 use crate::graphics::GraphicsContext;
 use crate::PlayState;
 
@@ -18,7 +18,7 @@ fn calculate_game_size(width: f32, height: f32, min_size: f32) -> f32 {
 
 
 pub struct GameTankBoyUI {
-    desired_scale: Option<u8>,
+    pub desired_scale: Option<u8>,
     screen: Box<TextureHandle>,
     textures: HashMap<String, TextureHandle>,
 
@@ -183,7 +183,7 @@ impl GameTankBoyUI {
                 if button.ui(ui).clicked() {
                     match emulator.play_state {
                         PlayState::WasmInit => { emulator.play_state = PlayState::Playing; }
-                        PlayState::Paused => { emulator.play_state = PlayState::Playing; }
+                        PlayState::Paused => { emulator.resume(); }
                         PlayState::Playing => { emulator.play_state = PlayState::Paused; }
                     }
                 }
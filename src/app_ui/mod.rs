@@ -1,3 +1,5 @@
 pub mod gametankboy;
 pub mod vram_viewer;
 pub mod ram_inspector;
+pub mod debugger;
+pub mod disassembly;
@@ -1,7 +1,8 @@
 use egui::{Align, Color32, Label, Layout, RichText, Ui};
 use egui_extras::Column;
-use crate::emulator::emulator::Emulator;
-use crate::emulator::gametank_bus::ByteDecorator;
+use gametank_core::debugger::Processor;
+use gametank_core::emulator::Emulator;
+use gametank_core::gametank_bus::ByteDecorator;
 
 pub struct MemoryInspector {
     // memory: [ByteDecorator; 0x8000]
@@ -46,11 +47,15 @@ impl MemoryInspector {
                                 ByteDecorator::Vram(b) => { (b, Color32::from_rgb(255, 255, 0)) },
                                 ByteDecorator::Framebuffer(b) => { (b, Color32::from_rgb(0, 255, 255)) },
                                 ByteDecorator::Aram(b) => { (b, Color32::from_rgb(255, 0, 255)) },
+                                ByteDecorator::VersatileInterfaceAdapter(b) => { (b, Color32::from_rgb(255, 128, 0)) },
                                 ByteDecorator::Unreadable(b) => { (b, Color32::from_rgb(128, 128, 128)) },
                             };
                             let t = RichText::new(format!("{:02X}", byte)).color(color);
 
-                            ui.label(t);
+                            let response = ui.label(t);
+                            if let Some(symbol) = emulator.symbol_at(Processor::Cpu, address as u16) {
+                                response.on_hover_text(symbol);
+                            }
                         });
                     }
                 });
@@ -2,7 +2,7 @@ use egui::{vec2, Align, Context, Direction, Frame, Image, Layout, ScrollArea, Se
 use egui::load::SizedTexture;
 use egui::scroll_area::ScrollBarVisibility;
 use egui::style::ScrollStyle;
-use crate::emulator::emulator::Emulator;
+use gametank_core::emulator::Emulator;
 
 pub enum VRAMViewerLayout {
     Pages
@@ -94,7 +94,7 @@ impl VRAMViewer {
                 };
 
 
-                let q1 = SizedTexture::new(self.vram_quads[0 + 4*page].id(), vec2(size, size));
+                let q1 = SizedTexture::new(self.vram_quads[4*page].id(), vec2(size, size));
                 let q2 = SizedTexture::new(self.vram_quads[1 + 4*page].id(), vec2(size, size));
                 let q3 = SizedTexture::new(self.vram_quads[2 + 4*page].id(), vec2(size, size));
                 let q4 = SizedTexture::new(self.vram_quads[3 + 4*page].id(), vec2(size, size));
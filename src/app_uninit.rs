@@ -19,9 +19,11 @@ use winit::event::{DeviceEvent, DeviceId, KeyEvent, StartCause, WindowEvent};
 use crate::app_initialized::AppInitialized;
 use crate::app_ui::gametankboy::GameTankBoyUI;
 // use crate::app_ui::ui_gametank;
-use crate::emulator::color_map::COLOR_MAP;
+use gametank_core::color_map::COLOR_MAP;
 use crate::egui_renderer::EguiRenderer;
-use crate::emulator::emulator::{Emulator, HEIGHT, WIDTH};
+use gametank_core::emulator::{Emulator, HEIGHT, WIDTH};
+use crate::audio_output::default_output;
+use gametank_core::gdb::GdbServer;
 use crate::graphics::GraphicsContext;
 
 pub struct App {
@@ -32,6 +34,10 @@ pub struct App {
 
     pub app_initialized: Option<AppInitialized>,
 
+    /// integer scale the game screen starts at
+    pub scale: u8,
+    pub gdb: Option<GdbServer>,
+
     pub gc_tx: mpsc::Sender<GraphicsContext>,
     pub gc_rx: mpsc::Receiver<GraphicsContext>,
 }
@@ -45,14 +51,19 @@ impl Default for App {
 impl App {
     pub fn new() -> Self {
         let (tx, rx) = mpsc::channel();
+        let mut emulator = Emulator::init();
+        emulator.audio_out = Some(default_output());
+
         Self {
-            emulator: Some(Emulator::init()),
+            emulator: Some(emulator),
             gc: None,
             window: None,
             egui_renderer: None,
             gc_tx: tx,
             gc_rx: rx,
             app_initialized: None,
+            scale: 6,
+            gdb: None,
         }
     }
 
@@ -127,7 +138,7 @@ impl App {
 
                 let fmt = gc.surface.get_current_texture().expect("ugh").texture.format();
 
-                self.egui_renderer = Some(EguiRenderer::new(device, fmt, None, 1, &window));
+                self.egui_renderer = Some(EguiRenderer::new(device, fmt, None, 1, window));
                 // let color_image = self.framebuffer_to_color_image(&self.emulator.cpu_bus.read_full_framebuffer());
                 self.gc = Some(gc);
                 info!("adapter has been set up");
@@ -0,0 +1,332 @@
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufWriter;
+use std::ops::{Index, IndexMut};
+use dasp_graph::{Buffer, Input, NodeData};
+use dasp_signal::Signal;
+use dasp_signal::interpolate::Converter;
+use cpal::traits::HostTrait;
+use gametank_core::AudioOutput;
+use gametank_core::audio_output::NullAudioOutput;
+use gametank_core::wav::WavWriter;
+use klingt::{AudioNode, Klingt};
+use klingt::nodes::sink::CpalMonoSink;
+use rtrb::{Consumer, Producer, RingBuffer};
+use tracing::{debug, error, trace, warn};
+use petgraph::prelude::NodeIndex;
+use crate::dac_filter::{DacFilter, DacFilterSettings};
+use crate::resampler::{Interpolation, Resampler};
+
+const OUTPUT_SAMPLE_RATE: u32 = 48000;
+
+pub struct GameTankSignal {
+    buffer: Consumer<u8>,
+}
+
+impl GameTankSignal {
+    pub fn new(buffer: Consumer<u8>) -> Self {
+        Self {
+            buffer,
+        }
+    }
+}
+
+impl Signal for GameTankSignal {
+    type Frame = f32;
+
+    fn next(&mut self) -> Self::Frame {
+        if let Ok(sample) = self.buffer.pop() {
+            (sample as f32 / 255.0) * 2.0 - 1.0
+        } else {
+            debug!("resampler ran out of acp samples");
+            0.0
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.buffer.slots() < 64
+    }
+}
+
+#[derive(Debug)]
+pub struct RtrbSource {
+    output_buffer: Consumer<Buffer>
+}
+
+/// The console's analog output stage, between the resampler and the sound card.
+pub struct DacStage {
+    filter: DacFilter,
+}
+
+pub struct GameTankAudio {
+    pub producer: Producer<u8>,
+
+    klingt: Klingt<GTNode>,
+
+    idx_in: NodeIndex,
+    idx_dac: NodeIndex,
+    idx_out: NodeIndex,
+
+    pub resampled: VecDeque<f32>,
+
+    pub output_queue: Producer<Buffer>, // ring buffer for output buffers
+
+    pub sample_rate: f64,
+    target_sample_rate: f64,
+    pub converter: Converter<GameTankSignal, Interpolation>,
+}
+
+impl GameTankAudio {
+    pub fn new(sample_rate: f64, target_sample_rate: f64, resampler: Resampler, dac_filter: DacFilterSettings) -> Self {
+        // caps out around 48kHz, but technically the system can go higher...
+        let (input_producer, input_buffer) = RingBuffer::<u8>::new(128); // Ring buffer to hold GameTank samples
+        let (output_producer, output_consumer) = RingBuffer::<Buffer>::new(512); // Ring buffer to hold output buffers
+        let interp = Interpolation::new(resampler, sample_rate, target_sample_rate);
+        let signal = GameTankSignal::new(input_buffer);
+        let converter = signal.from_hz_to_hz(interp, sample_rate, target_sample_rate);
+
+        let mut klingt = Klingt::default();
+
+        let sink = CpalMonoSink::default();
+        let out_node = NodeData::new1(GTNode::CpalMonoSink(sink));
+
+        let gt_node = NodeData::new1(GTNode::GameTankSource(RtrbSource{ output_buffer: output_consumer }));
+        let dac_node = NodeData::new1(GTNode::DacStage(DacStage { filter: DacFilter::new(dac_filter, target_sample_rate as f32) }));
+
+        let idx_in = klingt.add_node(gt_node);
+        let idx_dac = klingt.add_node(dac_node);
+        let idx_out = klingt.add_node(out_node);
+
+        klingt.add_edge(idx_in, idx_dac, ());
+        klingt.add_edge(idx_dac, idx_out, ());
+        
+        Self {
+            producer: input_producer,
+            klingt,
+            idx_in,
+            idx_dac,
+            idx_out,
+            resampled: VecDeque::with_capacity(1024),
+            output_queue: output_producer,
+            sample_rate,
+            target_sample_rate,
+            converter,
+        }
+    }
+
+    /// Stretches the output by `ratio`, for dynamic rate control.
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.converter.set_hz_to_hz(self.sample_rate, self.target_sample_rate * ratio);
+    }
+
+    pub fn set_dac_filter(&mut self, settings: DacFilterSettings) {
+        if let GTNode::DacStage(stage) = &mut self.klingt.index_mut(self.idx_dac).node {
+            stage.filter.set_settings(settings);
+        }
+    }
+
+    /// Resampled audio that hasn't reached the sound card yet, in seconds.
+    pub fn queued_seconds(&self) -> f64 {
+        let mut queued = self.resampled.len();
+        queued += (self.output_queue.buffer().capacity() - self.output_queue.slots()) * Buffer::LEN;
+        if let GTNode::CpalMonoSink(sink) = &self.klingt.index(self.idx_out).node {
+            queued += sink.buffer.buffer().capacity() - sink.buffer.slots();
+        }
+        queued as f64 / self.target_sample_rate
+    }
+
+    /// Resamples everything that's buffered, and records it to `capture` on the way through.
+    pub fn convert_to_output_buffers(&mut self, capture: &mut Option<WavWriter<BufWriter<File>>>) {
+        while !self.converter.is_exhausted() {
+            let sample = self.converter.next();
+            if let Some(wav) = capture {
+                if let Err(e) = wav.write_f32(sample) {
+                    error!("stopping output capture, couldn't write it: {e}");
+                    *capture = None;
+                }
+            }
+            self.resampled.push_back(sample);
+        }
+
+        while self.resampled.len() >= 64 && self.output_queue.slots() >= 8 {
+            if let Ok(chunk) = self.resampled.drain(..64).collect::<Vec<_>>().try_into() {
+                let mut buf = Buffer::SILENT;
+                for (b, v) in buf.iter_mut().zip::<[f32;64]>(chunk) {
+                    *b = v;
+                }
+                self.output_queue.push(buf).unwrap()
+            }
+        }
+    }
+
+    pub fn process_audio(&mut self) {
+        let mut ready_to_output = 0;
+        if let GTNode::GameTankSource(src) = &mut self.klingt.index_mut(self.idx_in).node {
+            ready_to_output = src.output_buffer.slots();
+        }
+
+        // Generate buffers in a loop
+        let mut can_output = false;
+        if let GTNode::CpalMonoSink(sink) = &mut self.klingt.index_mut(self.idx_out).node {
+            can_output = sink.buffer.slots() >= 64 && ready_to_output >= 4;
+        };
+
+        while can_output {
+            self.klingt.processor.process(&mut self.klingt.graph, self.idx_out);
+
+            if let GTNode::GameTankSource(src) = &mut self.klingt.index_mut(self.idx_in).node {
+                ready_to_output = src.output_buffer.slots();
+
+                if let GTNode::CpalMonoSink(sink) = &mut self.klingt.index_mut(self.idx_out).node {
+                    can_output = sink.buffer.slots() >= 64 && ready_to_output >= 4;
+                };
+                // sleep(Duration::from_millis(1)); // takes 1.33ms per 64 samples, so this should be safe
+                trace!("ready to output {ready_to_output}");
+            }
+        }
+    }
+}
+
+impl AudioNode for RtrbSource {
+    fn process(&mut self, _inputs: &[Input], output: &mut [Buffer]) {
+        let b = match self.output_buffer.pop() {
+            Ok(buf) => { buf }
+            Err(_) => { debug!("audio underrun"); Buffer::SILENT }
+        };
+        for buffer in output.iter_mut() {
+            *buffer = b.clone();
+        }
+        debug!("processed rtrb source");
+    }
+}
+
+impl AudioNode for DacStage {
+    fn process(&mut self, inputs: &[Input], output: &mut [Buffer]) {
+        let Some(input) = inputs.first() else {
+            return
+        };
+        for (in_buffer, out_buffer) in input.buffers().iter().zip(output.iter_mut()) {
+            for (i, o) in in_buffer.iter().zip(out_buffer.iter_mut()) {
+                *o = self.filter.process(*i);
+            }
+        }
+    }
+}
+
+/// Plays the emulator's ACP output through cpal, recreating the stream whenever the ACP's sample rate changes.
+#[derive(Default)]
+pub struct CpalAudioOutput {
+    audio: Option<GameTankAudio>,
+    capture: Option<WavWriter<BufWriter<File>>>,
+    rate_adjustment: Option<f64>,
+    resampler: Resampler,
+    dac_filter: DacFilterSettings,
+}
+
+impl CpalAudioOutput {
+    /// klingt panics if there's nowhere to play to, so check before making one.
+    pub fn device_available() -> bool {
+        cpal::default_host().default_output_device().is_some()
+    }
+
+    pub fn resampler(&self) -> Resampler {
+        self.resampler
+    }
+
+    pub fn set_resampler(&mut self, resampler: Resampler) {
+        if resampler != self.resampler {
+            self.resampler = resampler;
+            // picked up when the stream's recreated with the next sample
+            self.audio = None;
+        }
+    }
+
+    pub fn dac_filter(&self) -> DacFilterSettings {
+        self.dac_filter
+    }
+
+    pub fn set_dac_filter(&mut self, settings: DacFilterSettings) {
+        self.dac_filter = settings;
+        if let Some(audio) = &mut self.audio {
+            audio.set_dac_filter(settings);
+        }
+    }
+}
+
+/// cpal, or silence if there's no sound device.
+pub fn default_output() -> Box<dyn AudioOutput> {
+    if CpalAudioOutput::device_available() {
+        Box::new(CpalAudioOutput::default())
+    } else {
+        warn!("no audio output device, running without sound");
+        Box::new(NullAudioOutput)
+    }
+}
+
+/// The emulator's output, if it's playing through cpal and so has a resampler and dac filter to set.
+pub fn cpal_output(audio: &mut Option<Box<dyn AudioOutput>>) -> Option<&mut CpalAudioOutput> {
+    audio.as_mut()?.as_any_mut().downcast_mut()
+}
+
+impl AudioOutput for CpalAudioOutput {
+    fn push_sample(&mut self, sample: u8, sample_rate: f64) {
+        // if audio is none or mismatched sample rate
+        if self.audio.as_ref().is_none_or(|gta| gta.sample_rate != sample_rate) {
+            warn!("recreated audio stream with new sample rate: {:.3}Hz", sample_rate);
+            let mut audio = GameTankAudio::new(sample_rate, OUTPUT_SAMPLE_RATE as f64, self.resampler, self.dac_filter);
+            if let Some(ratio) = self.rate_adjustment {
+                audio.set_rate_adjustment(ratio);
+            }
+            self.audio = Some(audio);
+        }
+
+        if let Some(audio) = &mut self.audio {
+            if let Err(e) = audio.producer.push(sample) {
+                error!("not enough slots in audio producer: {e}");
+            }
+
+            audio.convert_to_output_buffers(&mut self.capture);
+            audio.process_audio();
+        }
+    }
+
+    fn output_sample_rate(&self) -> u32 {
+        OUTPUT_SAMPLE_RATE
+    }
+
+    fn capture_output(&mut self, wav: Option<WavWriter<BufWriter<File>>>) {
+        if let Some(mut old) = std::mem::replace(&mut self.capture, wav) {
+            if let Err(e) = old.finish() {
+                error!("couldn't finish output capture: {e}");
+            }
+        }
+    }
+
+    fn is_capturing_output(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    fn queued_seconds(&self) -> Option<f64> {
+        // nothing's playing until the first sample shows up, which is as empty as it gets
+        Some(self.audio.as_ref().map_or(0.0, |audio| audio.queued_seconds()))
+    }
+
+    fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.rate_adjustment = Some(ratio);
+        if let Some(audio) = &mut self.audio {
+            audio.set_rate_adjustment(ratio);
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[enum_delegate::implement(AudioNode, pub trait AudioNode { fn process(&mut self, inputs: &[Input], output: &mut [Buffer]);})]
+pub enum GTNode {
+    CpalMonoSink(CpalMonoSink),
+    GameTankSource(RtrbSource),
+    DacStage(DacStage),
+}
\ No newline at end of file
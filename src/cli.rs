@@ -0,0 +1,215 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use clap::Parser;
+use tracing::{error, warn, Level};
+use gametank_core::emulator::{Emulator, HEIGHT, WIDTH};
+use gametank_core::{AudioOutput, PlayState};
+use gametank_core::audio_output::{FileAudioOutput, NullAudioOutput};
+use gametank_core::trace::TraceArgs;
+use crate::audio_output::{cpal_output, default_output};
+use crate::dac_filter::DacFilterSettings;
+use crate::palette::Palette;
+use crate::resampler::Resampler;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioBackend {
+    Cpal,
+    Null,
+    File(PathBuf),
+}
+
+impl FromStr for AudioBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cpal" => Ok(AudioBackend::Cpal),
+            "null" => Ok(AudioBackend::Null),
+            _ => match s.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(AudioBackend::File(path.into())),
+                _ => Err(format!("unknown audio backend {s:?}, expected cpal, null or file:<path>")),
+            },
+        }
+    }
+}
+
+impl AudioBackend {
+    pub fn create(&self) -> Box<dyn AudioOutput> {
+        match self {
+            AudioBackend::Cpal => default_output(),
+            AudioBackend::Null => Box::new(NullAudioOutput),
+            AudioBackend::File(path) => Box::new(FileAudioOutput::new(path)),
+        }
+    }
+}
+
+/// GameTank: The Emulator!
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// .gtr rom to run, instead of the built-in game
+    pub rom: Option<PathBuf>,
+
+    /// ld65 .dbg or VICE label file to debug with (defaults to a .dbg/.lbl next to the rom)
+    #[arg(long)]
+    pub symbols: Option<PathBuf>,
+
+    /// don't start emulating until unpaused
+    #[arg(long)]
+    pub paused: bool,
+
+    /// integer scale for the game screen, when it fits in the window
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u8).range(1..))]
+    pub scale: u8,
+
+    /// color map to draw the screen with
+    #[arg(long, value_enum, default_value_t = Palette::Default)]
+    pub palette: Palette,
+
+    /// where sound goes: cpal, null, or file:<path> for a wav of the raw acp output
+    #[arg(long, conflicts_with = "no_audio")]
+    pub audio: Option<AudioBackend>,
+
+    /// run without sound, same as --audio null
+    #[arg(long)]
+    pub no_audio: bool,
+
+    /// pace emulation off the audio buffer instead of the wall clock, to stop crackle and drift
+    #[arg(long)]
+    pub audio_pacing: bool,
+
+    /// how to resample the acp's output for the sound card: linear, sinc[:TAPS] or blep[:TAPS]
+    #[arg(long, default_value_t = Resampler::Linear)]
+    pub resampler: Resampler,
+
+    /// play through a model of the console's analog output stage
+    #[arg(long)]
+    pub dac_filter: bool,
+
+    /// the dac filter's dc-blocking corner in hz, 0 to leave it out
+    #[arg(long, default_value_t = DacFilterSettings::default().high_pass_hz, requires = "dac_filter")]
+    pub dac_high_pass: f32,
+
+    /// the dac filter's low-pass corner in hz, 0 to leave it out
+    #[arg(long, default_value_t = DacFilterSettings::default().low_pass_hz, requires = "dac_filter")]
+    pub dac_low_pass: f32,
+
+    /// how far the dac filter lets the output move per millisecond, out of a range of 2.0. 0 to leave it out
+    #[arg(long, default_value_t = DacFilterSettings::default().slew_per_ms, requires = "dac_filter")]
+    pub dac_slew: f32,
+
+    /// record everything the acp sends the dac to this wav, at the rate it's sent
+    #[arg(long)]
+    pub capture_raw: Option<PathBuf>,
+
+    /// record the resampled 48khz stream that gets played to this wav
+    #[arg(long, conflicts_with_all = ["no_audio", "frames"])]
+    pub capture_output: Option<PathBuf>,
+
+    /// keep this many MiB of history for rewinding, 64 if no size is given
+    #[arg(long, value_name = "MIB", num_args = 0..=1, default_missing_value = "64")]
+    pub rewind: Option<usize>,
+
+    /// finish blits the moment they start
+    #[arg(long)]
+    pub instant_blit: bool,
+
+    /// one of error, warn, info, debug, trace
+    #[arg(long, default_value_t = Level::WARN)]
+    pub log_level: Level,
+
+    /// run this many frames without opening a window, then exit
+    #[arg(long)]
+    pub frames: Option<u64>,
+
+    /// serve the gdb remote protocol on this localhost port
+    #[arg(long, conflicts_with = "frames")]
+    pub gdb: Option<u16>,
+
+    /// where to save the screen as a png at the end of a headless run
+    #[arg(long, requires = "frames")]
+    pub screenshot: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
+}
+
+impl Args {
+    /// Applies the rom, symbol, trace, blitter, audio and pause options to a freshly made emulator.
+    pub fn configure(&self, emulator: &mut Emulator) -> Result<(), String> {
+        if let Some(rom) = &self.rom {
+            emulator.load_rom_file(rom).map_err(|e| format!("couldn't load {}: {e}", rom.display()))?;
+        }
+
+        if let Some(symbols) = &self.symbols {
+            emulator.load_symbols(symbols).map_err(|e| format!("couldn't load {}: {e}", symbols.display()))?;
+        }
+
+        emulator.tracer = self.trace.tracer()?;
+
+        emulator.instant_blit = self.instant_blit;
+        if let Some(mib) = self.rewind {
+            emulator.rewind.set_budget(mib * 1024 * 1024);
+        }
+        emulator.audio_pacing = self.audio_pacing;
+
+        if self.no_audio {
+            emulator.audio_out = Some(AudioBackend::Null.create());
+        } else if let Some(audio) = &self.audio {
+            emulator.audio_out = Some(audio.create());
+        }
+        if let Some(audio) = cpal_output(&mut emulator.audio_out) {
+            audio.set_resampler(self.resampler);
+            audio.set_dac_filter(DacFilterSettings {
+                bypass: !self.dac_filter,
+                high_pass_hz: self.dac_high_pass,
+                low_pass_hz: self.dac_low_pass,
+                slew_per_ms: self.dac_slew,
+            });
+        } else {
+            // only the cpal output resamples or filters; the others get the raw acp stream
+            if self.resampler != Resampler::default() {
+                warn!("--resampler only applies to cpal output, ignoring it");
+            }
+            if self.dac_filter {
+                warn!("--dac-filter only applies to cpal output, ignoring it");
+            }
+        }
+        if self.audio_pacing && emulator.audio_out.as_ref().is_none_or(|audio| audio.queued_seconds().is_none()) {
+            warn!("--audio-pacing needs an output with a buffer to pace off, falling back to the clock");
+        }
+
+        if self.capture_raw.is_some() || self.capture_output.is_some() {
+            emulator.start_audio_capture(self.capture_raw.as_deref(), self.capture_output.as_deref())
+                .map_err(|e| format!("couldn't start audio capture: {e}"))?;
+        }
+
+        emulator.play_state = if self.paused { PlayState::Paused } else { PlayState::Playing };
+        Ok(())
+    }
+
+    /// Runs `frames` frames as fast as possible, with no window. Returns the process' exit code.
+    pub fn run_headless(&self, frames: u64) -> i32 {
+        let mut emulator = Emulator::init();
+        if let Err(e) = self.configure(&mut emulator) {
+            error!("{e}");
+            return 1
+        }
+
+        for _ in 0..frames {
+            emulator.run_frame();
+        }
+        warn!("ran {} frames", frames);
+        emulator.stop_audio_capture();
+
+        if let Some(path) = &self.screenshot {
+            let pixels = Palette::selected().to_rgba(&emulator.cpu_bus.read_full_framebuffer());
+            if let Err(e) = image::save_buffer(path, &pixels, WIDTH, HEIGHT, image::ExtendedColorType::Rgba8) {
+                error!("couldn't save screenshot to {}: {e}", path.display());
+                return 1
+            }
+            warn!("saved screenshot to {}", path.display());
+        }
+        0
+    }
+}
@@ -0,0 +1,91 @@
+//! A rough model of what happens to the DAC's output on its way to the audio jack: an RC low-pass
+//! smoothing off the steps, an amp that can only slew so fast, and a coupling capacitor blocking DC.
+//! It runs after resampling, at the output's rate, in the cpal output's graph. The defaults are by ear, not measured off a board.
+
+/// The DAC stage's corners and slew rate. Setting any of them to 0 leaves that part out.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DacFilterSettings {
+    /// pass the audio through untouched
+    pub bypass: bool,
+    /// the coupling capacitor's corner, in hz
+    pub high_pass_hz: f32,
+    /// the RC filter's corner, in hz
+    pub low_pass_hz: f32,
+    /// how far the output can move in a millisecond, where the DAC's whole range is 2.0
+    pub slew_per_ms: f32,
+}
+
+impl Default for DacFilterSettings {
+    fn default() -> Self {
+        Self {
+            bypass: true,
+            high_pass_hz: 20.0,
+            low_pass_hz: 12000.0,
+            slew_per_ms: 20.0,
+        }
+    }
+}
+
+/// [`DacFilterSettings`] applied a sample at a time.
+#[derive(Clone, Debug)]
+pub struct DacFilter {
+    settings: DacFilterSettings,
+    sample_rate: f32,
+
+    low_pass: f32,
+    slewed: f32,
+    high_pass_in: f32,
+    high_pass_out: f32,
+}
+
+impl DacFilter {
+    pub fn new(settings: DacFilterSettings, sample_rate: f32) -> Self {
+        Self { settings, sample_rate, low_pass: 0.0, slewed: 0.0, high_pass_in: 0.0, high_pass_out: 0.0 }
+    }
+
+    pub fn settings(&self) -> DacFilterSettings {
+        self.settings
+    }
+
+    /// Changes the settings without resetting the filters, so there's no pop when they're tweaked while playing.
+    pub fn set_settings(&mut self, settings: DacFilterSettings) {
+        self.settings = settings;
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let dt = 1.0 / self.sample_rate;
+        let rc = |hz: f32| 1.0 / (2.0 * std::f32::consts::PI * hz);
+        let settings = self.settings;
+
+        // stages that are off (or bypassed) still follow along, so turning them back on doesn't pop
+        if settings.low_pass_hz > 0.0 {
+            self.low_pass += (sample - self.low_pass) * dt / (rc(settings.low_pass_hz) + dt);
+        } else {
+            self.low_pass = sample;
+        }
+
+        if settings.slew_per_ms > 0.0 {
+            let max_step = settings.slew_per_ms * 1000.0 * dt;
+            self.slewed += (self.low_pass - self.slewed).clamp(-max_step, max_step);
+        } else {
+            self.slewed = self.low_pass;
+        }
+
+        if settings.high_pass_hz > 0.0 {
+            let rc = rc(settings.high_pass_hz);
+            self.high_pass_out = rc / (rc + dt) * (self.high_pass_out + self.slewed - self.high_pass_in);
+        } else {
+            self.high_pass_out = self.slewed;
+        }
+        self.high_pass_in = self.slewed;
+
+        if settings.bypass {
+            sample
+        } else {
+            self.high_pass_out
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,75 @@
+//! Each stage of the DAC filter on its own, and bypassing them.
+
+use crate::dac_filter::{DacFilter, DacFilterSettings};
+
+const SAMPLE_RATE: f32 = 48_000.0;
+
+/// Every stage off, for tests to turn on just the one they're after.
+const NO_STAGES: DacFilterSettings = DacFilterSettings { bypass: false, high_pass_hz: 0.0, low_pass_hz: 0.0, slew_per_ms: 0.0 };
+
+fn run(filter: &mut DacFilter, input: impl IntoIterator<Item = f32>) -> Vec<f32> {
+    input.into_iter().map(|sample| filter.process(sample)).collect()
+}
+
+fn seconds(s: f32) -> usize {
+    (s * SAMPLE_RATE) as usize
+}
+
+#[test]
+fn no_stages_pass_the_input_through() {
+    let mut filter = DacFilter::new(NO_STAGES, SAMPLE_RATE);
+    let input = [0.0, 1.0, -1.0, 0.25, 0.25, -0.5];
+    assert_eq!(run(&mut filter, input), input);
+}
+
+#[test]
+fn low_pass_rounds_off_steps_and_cuts_highs() {
+    let mut filter = DacFilter::new(DacFilterSettings { low_pass_hz: 1000.0, ..NO_STAGES }, SAMPLE_RATE);
+    let step = run(&mut filter, std::iter::repeat_n(1.0, seconds(0.01)));
+    assert!(step[0] > 0.0 && step[0] < 0.2, "the step went straight through, to {}", step[0]);
+    assert!(step.windows(2).all(|w| w[1] >= w[0]), "an rc filter's step shouldn't overshoot");
+    assert!((step.last().unwrap() - 1.0).abs() < 1e-3, "the step never got there");
+
+    // a tone at nyquist is well past the corner
+    let mut filter = DacFilter::new(DacFilterSettings { low_pass_hz: 1000.0, ..NO_STAGES }, SAMPLE_RATE);
+    let nyquist = run(&mut filter, (0..seconds(0.01)).map(|i| if i % 2 == 0 { 1.0 } else { -1.0 }));
+    let peak = nyquist[seconds(0.005)..].iter().map(|s| s.abs()).fold(0.0, f32::max);
+    assert!(peak < 0.1, "nyquist only came down to {peak}");
+}
+
+#[test]
+fn high_pass_blocks_dc() {
+    let mut filter = DacFilter::new(DacFilterSettings { high_pass_hz: 20.0, ..NO_STAGES }, SAMPLE_RATE);
+    let dc = run(&mut filter, std::iter::repeat_n(1.0, seconds(0.5)));
+    assert!(dc[0] > 0.99, "the start of a step should get through the capacitor");
+    assert!(dc.last().unwrap().abs() < 1e-3, "dc leaked through, at {}", dc.last().unwrap());
+
+    // and a tone well above the corner mostly gets through
+    let mut filter = DacFilter::new(DacFilterSettings { high_pass_hz: 20.0, ..NO_STAGES }, SAMPLE_RATE);
+    let tone = run(&mut filter, (0..seconds(0.1)).map(|i| if (i / 24) % 2 == 0 { 1.0 } else { -1.0 }));
+    let peak = tone[seconds(0.05)..].iter().map(|s| s.abs()).fold(0.0, f32::max);
+    assert!(peak > 0.95, "a 1khz square came out at {peak}");
+}
+
+#[test]
+fn slew_limits_how_fast_the_output_moves() {
+    let mut filter = DacFilter::new(DacFilterSettings { slew_per_ms: 20.0, ..NO_STAGES }, SAMPLE_RATE);
+    let step = 20.0 * 1000.0 / SAMPLE_RATE;
+    let out = run(&mut filter, [1.0, 1.0, 1.0, 1.0, -1.0]);
+    assert_eq!(out[..4], [step, 2.0 * step, 1.0, 1.0]);
+    assert_eq!(out[4], 1.0 - step, "the fall wasn't limited like the rise");
+}
+
+#[test]
+fn bypass_passes_the_input_but_keeps_the_stages_following() {
+    let settings = DacFilterSettings { bypass: true, low_pass_hz: 1000.0, slew_per_ms: 20.0, high_pass_hz: 0.0 };
+    let mut filter = DacFilter::new(settings, SAMPLE_RATE);
+    let input = [0.0, 1.0, -1.0, 0.5];
+    assert_eq!(run(&mut filter, input), input);
+
+    // so turning it off after a while doesn't pop
+    run(&mut filter, std::iter::repeat_n(0.5, seconds(0.01)));
+    filter.set_settings(DacFilterSettings { bypass: false, ..settings });
+    assert_eq!(filter.settings(), DacFilterSettings { bypass: false, ..settings });
+    assert!((filter.process(0.5) - 0.5).abs() < 1e-3);
+}
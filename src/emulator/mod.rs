@@ -1,15 +0,0 @@
-use std::fmt::Debug;
-use crate::emulator::gametank_bus::Bus;
-use crate::input::ControllerButton::*;
-use crate::input::InputCommand::*;
-#[allow(clippy::unusual_byte_groupings)]
-
-pub mod color_map;
-pub mod blitter;
-pub mod audio_output;
-pub mod gamepad;
-pub mod gametank_bus;
-pub mod cartridges;
-pub mod emulator;
-
-
@@ -3,7 +3,7 @@ use tracing::error;
 use winit::window::Window;
 use wgpu::{CreateSurfaceError, Features, Limits, MemoryHints, Surface};
 use wgpu::Backend::Gl;
-use crate::emulator::emulator::{HEIGHT, WIDTH};
+use gametank_core::emulator::{HEIGHT, WIDTH};
 
 pub struct GraphicsContext {
     pub _instance: wgpu::Instance,
@@ -1,77 +1,42 @@
-use KeyState::{Held, JustPressed, JustReleased, Released};
+use std::collections::HashMap;
+use winit::keyboard::{Key, NamedKey, SmolStr};
+use gametank_core::input::ControllerButton::{Down, Left, Right, Start, Up, A, B, C};
+use gametank_core::input::InputCommand;
 
-#[derive(Copy, Clone, Debug)]
-#[derive(Eq, Hash, PartialEq)]
-pub enum ControllerButton {
-    Up,
-    Down,
-    Left,
-    Right,
-    B,
-    A,
-    Start,
-    C,
-}
+pub fn default_bindings() -> HashMap<Key, InputCommand> {
+    let mut input_bindings = HashMap::new();
 
-#[derive(Copy, Clone, Debug)]
-#[derive(Eq, Hash, PartialEq)]
-pub enum InputCommand {
-    Controller1(ControllerButton),
-    Controller2(ControllerButton),
-    PlayPause,
-    SoftReset,
-    HardReset,
-}
+    // controller 1
+    input_bindings.insert(Key::Named(NamedKey::Enter), InputCommand::Controller1(Start));
+    input_bindings.insert(Key::Named(NamedKey::ArrowLeft), InputCommand::Controller1(Left));
+    input_bindings.insert(Key::Named(NamedKey::ArrowRight), InputCommand::Controller1(Right));
+    input_bindings.insert(Key::Named(NamedKey::ArrowUp), InputCommand::Controller1(Up));
+    input_bindings.insert(Key::Named(NamedKey::ArrowDown), InputCommand::Controller1(Down));
+    input_bindings.insert(Key::Character(SmolStr::new("z")), InputCommand::Controller1(A));
+    input_bindings.insert(Key::Character(SmolStr::new("x")), InputCommand::Controller1(B));
+    input_bindings.insert(Key::Character(SmolStr::new("c")), InputCommand::Controller1(C));
 
-#[derive(Copy, Clone, Debug)]
-#[derive(Eq, Hash, PartialEq)]
-pub enum KeyState {
-    JustPressed,
-    Held,
-    JustReleased,
-    Released
-}
+    // controller 2
+    input_bindings.insert(Key::Named(NamedKey::Space), InputCommand::Controller2(Start));
+    input_bindings.insert(Key::Character(SmolStr::new("a")), InputCommand::Controller2(Left));
+    input_bindings.insert(Key::Character(SmolStr::new("d")), InputCommand::Controller2(Right));
+    input_bindings.insert(Key::Character(SmolStr::new("w")), InputCommand::Controller2(Up));
+    input_bindings.insert(Key::Character(SmolStr::new("s")), InputCommand::Controller2(Down));
+    input_bindings.insert(Key::Character(SmolStr::new("j")), InputCommand::Controller2(A));
+    input_bindings.insert(Key::Character(SmolStr::new("k")), InputCommand::Controller2(B));
+    input_bindings.insert(Key::Character(SmolStr::new("l")), InputCommand::Controller2(C));
 
-impl KeyState {
-    pub fn is_pressed(&self) -> bool {
-        match self {
-            JustPressed => { true }
-            Held => { true }
-            JustReleased => { false }
-            Released => { false }
-        }
-    }
+    // emulator
+    input_bindings.insert(Key::Character(SmolStr::new("r")), InputCommand::SoftReset);
+    input_bindings.insert(Key::Character(SmolStr::new("R")), InputCommand::HardReset);
+    input_bindings.insert(Key::Character(SmolStr::new("p")), InputCommand::PlayPause);
+    input_bindings.insert(Key::Named(NamedKey::Backspace), InputCommand::Rewind);
 
-    pub fn new(pressed: bool) -> Self {
-        if pressed {
-            return JustPressed
-        }
-        Released
+    // save states: F1-F4 quick-save, F5-F8 quick-load
+    for (slot, (save, load)) in [(NamedKey::F1, NamedKey::F5), (NamedKey::F2, NamedKey::F6), (NamedKey::F3, NamedKey::F7), (NamedKey::F4, NamedKey::F8)].into_iter().enumerate() {
+        input_bindings.insert(Key::Named(save), InputCommand::QuickSave(slot as u8));
+        input_bindings.insert(Key::Named(load), InputCommand::QuickLoad(slot as u8));
     }
-    
-    pub fn update_state(&self, pressed: bool) -> Self {
-        if pressed {
-            return match self {
-                JustPressed => { JustPressed }
-                Held => { Held }
-                JustReleased => { JustPressed }
-                Released => { JustPressed }
-            }
-        }
-        match self {
-            JustPressed => { JustReleased }
-            Held => { JustReleased }
-            JustReleased => { Released }
-            Released => { Released }
-        }
-    } 
 
-    pub fn update(&self) -> Self {
-        match self {
-            JustPressed => { Held }
-            Held => { Held }
-            JustReleased => { Released }
-            Released => { Released }
-        }
-    }
-}
\ No newline at end of file
+    input_bindings
+}
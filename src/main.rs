@@ -1,8 +1,6 @@
-#![feature(core_intrinsics)]
 #![allow(clippy::disallowed_methods, clippy::single_match)]
-#![allow(dead_code, unused_variables, unused_imports, internal_features)]
+#![allow(dead_code, unused_variables, unused_imports)]
 
-mod helpers;
 mod input;
 mod app_uninit;
 mod egui_renderer;
@@ -10,8 +8,12 @@ mod graphics;
 mod app_ui;
 pub mod app_initialized;
 mod app_delegation;
-
-mod emulator;
+mod audio_output;
+mod resampler;
+mod dac_filter;
+mod palette;
+#[cfg(not(target_arch = "wasm32"))]
+mod cli;
 
 use app_delegation::DelegatedApp::Uninitialized;
 use std::cmp::PartialEq;
@@ -35,17 +37,10 @@ use std::future::Future;
 #[cfg(target_arch = "wasm32")]
 use web_sys::Event;
 use crate::app_uninit::App;
-use crate::PlayState::*;
-
-//
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum PlayState {
-    WasmInit,
-    Paused,
-    Playing,
-}
 
-fn setup_logging() {
+pub use gametank_core::PlayState;
+
+fn setup_logging(level: Level) {
     #[cfg(target_arch = "wasm32")]
     {
         use tracing_wasm::{WASMLayer, WASMLayerConfigBuilder};
@@ -53,7 +48,7 @@ fn setup_logging() {
 
         // Set up the WASM layer for tracing logs
         let wlconfig = WASMLayerConfigBuilder::new()
-            .set_max_level(Level::WARN).build();
+            .set_max_level(level).build();
 
         let wasm_layer = WASMLayer::new(wlconfig);
         // Configure the subscriber with the WASM layer
@@ -65,7 +60,7 @@ fn setup_logging() {
     #[cfg(not(target_arch = "wasm32"))]
     {
         tracing_subscriber::fmt()
-            .with_max_level(Level::WARN)
+            .with_max_level(level)
             .compact()
             .finish()
             .init();
@@ -88,7 +83,7 @@ pub fn wasm_main() {
         }
     }));
 
-    setup_logging();
+    setup_logging(Level::WARN);
     info!("console logger started.");
 
     let event_loop = EventLoop::<()>::with_user_event().build().unwrap();
@@ -109,9 +104,17 @@ pub fn main() {
 
 
     #[cfg(not(target_arch = "wasm32"))] {
-        setup_logging();
+        use clap::Parser;
+        let args = cli::Args::parse();
+
+        setup_logging(args.log_level);
         info!("stdout logger started");
 
+        args.palette.select();
+        if let Some(frames) = args.frames {
+            std::process::exit(args.run_headless(frames));
+        }
+
         let event_loop = EventLoop::<()>::with_user_event().build().unwrap();
         event_loop.set_control_flow(ControlFlow::Poll);
 
@@ -119,8 +122,22 @@ pub fn main() {
         // if it didn't work, oh well
         let _ = set_current_thread_priority(ThreadPriority::Max);
 
-        let mut app = Uninitialized(App::new());
-        // TODO: app.emulator.as_mut().unwrap().play_state = Playing;
+        let mut app = App::new();
+        app.scale = args.scale;
+        if let Err(e) = args.configure(app.emulator.as_mut().unwrap()) {
+            error!("{e}");
+            std::process::exit(1);
+        }
+        if let Some(port) = args.gdb {
+            match gametank_core::gdb::GdbServer::bind(port) {
+                Ok(server) => app.gdb = Some(server),
+                Err(e) => {
+                    error!("couldn't start gdb server on port {port}: {e}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        let mut app = Uninitialized(app);
 
         let _ = event_loop.run_app(&mut app);
     }
@@ -0,0 +1,44 @@
+use std::cell::Cell;
+use gametank_core::color_map::{COLOR_MAP, COLOR_MAP_PERCEPTUALLY_AUTOMAPPED, COLOR_MAP_WRONG};
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(clap::ValueEnum))]
+pub enum Palette {
+    #[default]
+    Default,
+    Perceptual,
+    Wrong,
+}
+
+thread_local! {
+    static SELECTED_PALETTE: Cell<Palette> = const { Cell::new(Palette::Default) };
+}
+
+impl Palette {
+    /// The palette every framebuffer/vram view gets drawn with.
+    pub fn selected() -> Self {
+        SELECTED_PALETTE.with(|p| p.get())
+    }
+
+    pub fn select(self) {
+        SELECTED_PALETTE.with(|p| p.set(self));
+    }
+
+    pub fn colors(self) -> &'static [(u8, u8, u8, u8); 256] {
+        match self {
+            Palette::Default => &COLOR_MAP,
+            Palette::Perceptual => &COLOR_MAP_PERCEPTUALLY_AUTOMAPPED,
+            Palette::Wrong => &COLOR_MAP_WRONG,
+        }
+    }
+
+    pub fn to_rgba(self, framebuffer: &[u8; 128*128]) -> Vec<u8> {
+        let colors = self.colors();
+        let mut pixels: Vec<u8> = Vec::with_capacity(128 * 128 * 4); // 4 channels per pixel (RGBA)
+        for &index in framebuffer.iter() {
+            let (r, g, b, a) = colors[index as usize];
+            pixels.extend([r, g, b, a]);
+        }
+        pixels
+    }
+}
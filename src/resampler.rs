@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use dasp_interpolate::Interpolator;
+use dasp_interpolate::linear::Linear;
+
+/// how finely the kernel's tabulated, per tap
+const TABLE_POINTS_PER_TAP: usize = 64;
+
+/// How an output gets from the ACP's rate to its own. The ACP's rate is whatever the game sets
+/// `SystemControl::sample_rate` to, so it's rarely a nice ratio, and its square-ish waves alias
+/// badly without some band-limiting.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Resampler {
+    /// straight lines between samples, cheap and aliased
+    #[default]
+    Linear,
+    /// windowed sinc, for smooth band-limited playback of the samples
+    Sinc { taps: usize },
+    /// band-limited steps, which keeps the DAC's hard edges but filters them to what the output can play
+    BandLimitedStep { taps: usize },
+}
+
+impl Resampler {
+    pub const DEFAULT_TAPS: usize = 16;
+    pub const MAX_TAPS: usize = 64;
+
+    pub fn taps(&self) -> Option<usize> {
+        match self {
+            Resampler::Linear => None,
+            Resampler::Sinc { taps } | Resampler::BandLimitedStep { taps } => Some(*taps),
+        }
+    }
+}
+
+impl Display for Resampler {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Resampler::Linear => write!(f, "linear"),
+            Resampler::Sinc { taps } => write!(f, "sinc:{taps}"),
+            Resampler::BandLimitedStep { taps } => write!(f, "blep:{taps}"),
+        }
+    }
+}
+
+impl FromStr for Resampler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, taps) = match s.split_once(':') {
+            Some((kind, taps)) => {
+                let taps = taps.parse::<usize>().map_err(|_| format!("bad tap count in {s:?}"))?;
+                if !(2..=Self::MAX_TAPS).contains(&taps) || taps % 2 != 0 {
+                    return Err(format!("tap count in {s:?} should be even, between 2 and {}", Self::MAX_TAPS))
+                }
+                (kind, taps)
+            }
+            None => (s, Self::DEFAULT_TAPS),
+        };
+
+        match kind {
+            "linear" if !s.contains(':') => Ok(Resampler::Linear),
+            "sinc" => Ok(Resampler::Sinc { taps }),
+            "blep" => Ok(Resampler::BandLimitedStep { taps }),
+            _ => Err(format!("unknown resampler {s:?}, expected linear, sinc[:TAPS] or blep[:TAPS]")),
+        }
+    }
+}
+
+/// The interpolator behind each [`Resampler`], for a `dasp_signal` converter.
+pub enum Interpolation {
+    Linear(Linear<f32>),
+    Windowed(Windowed),
+}
+
+impl Interpolation {
+    pub fn new(resampler: Resampler, source_hz: f64, target_hz: f64) -> Self {
+        // cutoff in cycles per source sample
+        let output_nyquist = 0.5 * target_hz / source_hz;
+        match resampler {
+            Resampler::Linear => Interpolation::Linear(Linear::new(0.0, 0.0)),
+            Resampler::Sinc { taps } => Interpolation::Windowed(Windowed::new(Kernel::Sinc, taps, output_nyquist.min(0.5))),
+            Resampler::BandLimitedStep { taps } => Interpolation::Windowed(Windowed::new(Kernel::Step, taps, output_nyquist)),
+        }
+    }
+}
+
+impl Interpolator for Interpolation {
+    type Frame = f32;
+
+    fn interpolate(&self, x: f64) -> f32 {
+        match self {
+            Interpolation::Linear(linear) => linear.interpolate(x),
+            Interpolation::Windowed(windowed) => windowed.interpolate(x),
+        }
+    }
+
+    fn next_source_frame(&mut self, source_frame: f32) {
+        match self {
+            Interpolation::Linear(linear) => linear.next_source_frame(source_frame),
+            Interpolation::Windowed(windowed) => windowed.next_source_frame(source_frame),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kernel {
+    /// treats samples as points on a smooth curve
+    Sinc,
+    /// treats samples as levels held until the next one, like the DAC does
+    Step,
+}
+
+/// Sinc and band-limited step interpolation, sharing a Blackman-windowed kernel that's tabulated up front.
+pub struct Windowed {
+    kernel: Kernel,
+    /// the last few source frames, with the two being interpolated between in the middle
+    history: VecDeque<f32>,
+    /// the kernel (or its running integral, for steps) from `-width` to `width` source samples
+    table: Vec<f32>,
+    width: f64,
+}
+
+impl Windowed {
+    fn new(kernel: Kernel, taps: usize, cutoff: f64) -> Self {
+        // `taps` zero crossings across the whole kernel, however wide that is in source samples
+        let width = taps as f64 / (4.0 * cutoff);
+        let points = taps * TABLE_POINTS_PER_TAP + 1;
+
+        let mut table: Vec<f32> = (0..points).map(|i| {
+            let t = (i as f64 / (points - 1) as f64) * 2.0 - 1.0;
+            let x = 2.0 * cutoff * t * width;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+            let blackman = 0.42 + 0.5 * (PI * t).cos() + 0.08 * (2.0 * PI * t).cos();
+            (2.0 * cutoff * sinc * blackman) as f32
+        }).collect();
+
+        if kernel == Kernel::Step {
+            // integrate the kernel into a step, and make sure it finishes at exactly 1
+            let mut total = 0.0;
+            let mut previous = 0.0;
+            for k in table.iter_mut() {
+                total += (previous + *k) / 2.0;
+                previous = *k;
+                *k = total;
+            }
+            for k in table.iter_mut() {
+                *k /= total;
+            }
+        }
+
+        // enough frames that everything the kernel reaches is there, whichever way it's facing
+        let reach = width.ceil() as usize + 2;
+        Self {
+            kernel,
+            history: VecDeque::from(vec![0.0; reach * 2]),
+            table,
+            width,
+        }
+    }
+
+    /// The tabulated kernel at `t` source samples from its center.
+    fn lookup(&self, t: f64) -> f32 {
+        if t <= -self.width {
+            return self.table[0]
+        }
+        if t >= self.width {
+            return self.table[self.table.len() - 1]
+        }
+        let position = (t + self.width) / (2.0 * self.width) * (self.table.len() - 1) as f64;
+        let i = position as usize;
+        let fraction = (position - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * fraction
+    }
+
+    fn interpolate(&self, x: f64) -> f32 {
+        // the frame being interpolated away from is at time 0, the one before it at -1, and so on
+        let left = self.history.len() / 2 - 1;
+        let time = |i: usize| i as f64 - left as f64;
+
+        match self.kernel {
+            Kernel::Sinc => {
+                // the weights only sum to 1 between samples with a lot of taps, so scale them there,
+                // or a constant level comes out rippling at the source rate
+                let (out, total) = self.history.iter().enumerate()
+                    .map(|(i, &frame)| (frame, self.lookup(x - time(i))))
+                    .fold((0.0, 0.0), |(out, total), (frame, weight)| (out + frame * weight, total + weight));
+                out / total
+            }
+            Kernel::Step => {
+                // start from the oldest level, and add each change as a step that's been band-limited
+                let mut out = self.history[0];
+                for i in 1..self.history.len() {
+                    out += (self.history[i] - self.history[i - 1]) * self.lookup(x - time(i));
+                }
+                out
+            }
+        }
+    }
+
+    fn next_source_frame(&mut self, source_frame: f32) {
+        self.history.pop_front();
+        self.history.push_back(source_frame);
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,56 @@
+//! Each resampler at rates above, below and near the output's, fed a constant level.
+
+use dasp_signal::Signal;
+use dasp_signal::interpolate::Converter;
+use crate::resampler::{Interpolation, Resampler};
+
+const RESAMPLERS: [Resampler; 5] = [
+    Resampler::Linear,
+    Resampler::Sinc { taps: Resampler::DEFAULT_TAPS },
+    Resampler::Sinc { taps: 2 },
+    Resampler::BandLimitedStep { taps: Resampler::DEFAULT_TAPS },
+    Resampler::BandLimitedStep { taps: Resampler::MAX_TAPS },
+];
+
+/// source and output rates, in hz
+const RATES: [(f64, f64); 4] = [(13_982.0, 48_000.0), (48_000.0, 44_100.0), (96_000.0, 48_000.0), (44_100.0, 44_100.0)];
+
+/// A second of `level` at `source_hz`, resampled to `target_hz`.
+fn resample(resampler: Resampler, source_hz: f64, target_hz: f64, level: f32) -> Vec<f32> {
+    let source = dasp_signal::from_iter(std::iter::repeat_n(level, source_hz as usize));
+    let interpolation = Interpolation::new(resampler, source_hz, target_hz);
+    Converter::from_hz_to_hz(source, interpolation, source_hz, target_hz).until_exhausted().collect()
+}
+
+#[test]
+fn output_length_follows_the_rate_ratio() {
+    for resampler in RESAMPLERS {
+        for (source_hz, target_hz) in RATES {
+            let samples = resample(resampler, source_hz, target_hz, 0.5).len();
+            // give or take the source sample that's still being interpolated towards
+            let slack = (target_hz / source_hz).ceil() as usize + 1;
+            assert!(samples.abs_diff(target_hz as usize) <= slack, "{resampler} from {source_hz}hz to {target_hz}hz made {samples} samples");
+        }
+    }
+}
+
+#[test]
+fn dc_stays_dc() {
+    for resampler in RESAMPLERS {
+        for (source_hz, target_hz) in RATES {
+            let samples = resample(resampler, source_hz, target_hz, 0.5);
+            // past where the kernel's still reaching back into the silence before the input started
+            let settled = &samples[target_hz as usize / 100..];
+            let worst = settled.iter().map(|s| (s - 0.5).abs()).fold(0.0, f32::max);
+            assert!(worst < 0.005, "{resampler} from {source_hz}hz to {target_hz}hz is off by up to {worst}");
+        }
+    }
+}
+
+#[test]
+fn silence_stays_silent() {
+    for resampler in RESAMPLERS {
+        let samples = resample(resampler, 13_982.0, 48_000.0, 0.0);
+        assert!(samples.iter().all(|&s| s == 0.0), "{resampler} made noise out of nothing");
+    }
+}